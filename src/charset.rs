@@ -0,0 +1,123 @@
+//! Works out the character encoding of a feed and turns it into UTF-8 before it is parsed.
+//!
+//! Following RFC 7303 the byte order mark wins, then the `charset` of the `Content-Type` header,
+//! then the `encoding` of the XML declaration, and UTF-8 when nothing says otherwise. Labels are
+//! read the way browsers do, so `ISO-8859-1` is decoded as `windows-1252`.
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use regex::{bytes, Regex};
+
+/// Decodes the body of a response, the XML declaration of the result says UTF-8
+pub(crate) fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let (encoding, bom) = match Encoding::for_bom(body) {
+        Some((encoding, length)) => (encoding, length),
+        None => (
+            content_type
+                .and_then(charset)
+                .or_else(|| declared(body))
+                .unwrap_or(UTF_8),
+            0,
+        ),
+    };
+
+    let (text, _) = encoding.decode_without_bom_handling(&body[bom..]);
+    declare_utf8(&text)
+}
+
+/// `charset` parameter of a `Content-Type` header
+fn charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches('"').as_bytes())
+    })
+}
+
+/// `encoding` of the XML declaration
+fn declared(body: &[u8]) -> Option<&'static Encoding> {
+    let declaration =
+        bytes::Regex::new(r#"^\s*<\?xml[^>]*?\sencoding\s*=\s*["']([A-Za-z0-9._:-]+)["']"#)
+            .unwrap();
+    let head = &body[..body.len().min(1024)];
+    let label = declaration.captures(head)?.get(1)?.as_bytes();
+
+    // a document read this far as ASCII isn't UTF-16, whatever it says
+    Encoding::for_label(label).filter(|encoding| *encoding != UTF_16LE && *encoding != UTF_16BE)
+}
+
+/// Makes the XML declaration agree with the text, the parsers read the declared encoding
+fn declare_utf8(text: &str) -> String {
+    let encoding =
+        Regex::new(r#"^(\s*<\?xml[^>]*?\sencoding\s*=\s*["'])[A-Za-z0-9._:-]+(["'])"#).unwrap();
+    encoding.replace(text, "${1}UTF-8${2}").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Title of the channel of the decoded feed, as the RSS parser reads it
+    fn title(body: &[u8], content_type: Option<&str>) -> String {
+        let text = decode(body, content_type);
+        rss::Channel::read_from(text.as_bytes())
+            .unwrap()
+            .title()
+            .to_string()
+    }
+
+    fn feed(declaration: &str, title: &str) -> String {
+        format!(
+            "{}<rss version=\"2.0\"><channel><title>{}</title><link>https://example.com/</link><description>d</description></channel></rss>",
+            declaration, title
+        )
+    }
+
+    #[test]
+    fn utf8_bom() {
+        let mut body = b"\xEF\xBB\xBF".to_vec();
+        body.extend(feed("<?xml version=\"1.0\"?>", "Café").as_bytes());
+        // the BOM wins over a wrong header
+        assert_eq!(title(&body, Some("text/xml; charset=ISO-8859-1")), "Café");
+    }
+
+    #[test]
+    fn utf16_bom() {
+        let text = feed("<?xml version=\"1.0\" encoding=\"UTF-16\"?>", "Grüße €");
+        let mut body = vec![0xFF, 0xFE];
+        body.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(title(&body, None), "Grüße €");
+
+        let mut body = vec![0xFE, 0xFF];
+        body.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(title(&body, None), "Grüße €");
+    }
+
+    #[test]
+    fn header_charset_wins_over_the_declaration() {
+        let body = feed("<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>", "Café €");
+        assert_eq!(
+            title(
+                body.as_bytes(),
+                Some("application/rss+xml; charset=\"utf-8\"")
+            ),
+            "Café €"
+        );
+    }
+
+    #[test]
+    fn windows_1252_declaration_without_header() {
+        let body = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?>\
+            <rss version=\"2.0\"><channel><title>\x93Smart\x94 caf\xE9 \x96 5 \x80</title>\
+            <link>https://example.com/</link><description>d</description></channel></rss>";
+        assert_eq!(title(body, None), "\u{201c}Smart\u{201d} café \u{2013} 5 €");
+        assert!(decode(body, None).starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+    }
+
+    #[test]
+    fn no_declaration_is_utf8() {
+        let body = feed("", "Zoë ✓");
+        assert_eq!(title(body.as_bytes(), None), "Zoë ✓");
+        assert_eq!(title(body.as_bytes(), Some("text/xml")), "Zoë ✓");
+    }
+}
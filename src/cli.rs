@@ -0,0 +1,234 @@
+//! Commands run without opening the reader, `trss <command> [options]`.
+use crate::{
+    config::{self, Config},
+    digest,
+    epub::{self, Section},
+    error::Error,
+    export,
+    filter::Filter,
+    query,
+    rss::{example_feed, Fetcher, Website},
+    serve, state,
+};
+
+use jiff::Timestamp;
+use std::{cmp::Reverse, slice::Iter, sync::Mutex, thread};
+
+/// Subscriptions fetched at the same time
+const WORKERS: usize = 4;
+
+const USAGE: &str = "Usage: trss [<command> [<options>]]
+
+Without a command trss opens the reader.
+
+Commands:
+  export-epub [--unread] [--feed <name or URL>]... -o <file>
+      Packs the articles of the subscriptions into an EPUB book, only the unread ones with
+      --unread and only the ones of some feeds with --feed
+  digest [--since <duration>] [--format md|html] [--feed <name or URL>]...
+      Prints the articles published in the last 24h, or the duration given like 12h, 7d or 2w,
+      grouped by feed with their first paragraph. Articles already read are left out
+  serve [--bind <address:port>]
+      Serves every subscription merged, each folder, each virtual feed and the starred articles
+      as Atom, RSS and JSON feeds, at 127.0.0.1:8080 unless told otherwise. The list is at /
+  help
+      Shows this message
+";
+
+/// Runs a command given on the command line
+pub(crate) fn run(command: &str, arguments: &[String]) -> Result<(), Error> {
+    match command {
+        "export-epub" => export_epub(arguments),
+        "digest" => digest(arguments),
+        "serve" => serve(arguments),
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(usage(&format!("unknown command {}", command))),
+    }
+}
+
+fn usage(message: &str) -> Error {
+    Error::UsageError(format!("{}\n\n{}", message, USAGE))
+}
+
+/// Value following an option
+fn value(arguments: &mut Iter<String>, option: &str) -> Result<String, Error> {
+    arguments
+        .next()
+        .cloned()
+        .ok_or_else(|| usage(&format!("{} needs a value", option)))
+}
+
+fn export_epub(arguments: &[String]) -> Result<(), Error> {
+    let (mut unread, mut feeds, mut output) = (false, vec![], None);
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--unread" => unread = true,
+            "--feed" => feeds.push(value(&mut arguments, "--feed")?),
+            "-o" | "--output" => output = Some(value(&mut arguments, "-o")?),
+            other => return Err(usage(&format!("unexpected argument {}", other))),
+        }
+    }
+    let output = output.ok_or_else(|| usage("the book needs a file name, -o <file>"))?;
+
+    let config = config::load()?;
+    let state = state::load()?;
+    let sections: Vec<Section> = fetch(&config, &feeds)?
+        .into_iter()
+        .map(|website| Section {
+            feed: website.name,
+            articles: website
+                .articles
+                .into_iter()
+                .filter(|article| !unread || !state.is_read(article))
+                .collect(),
+        })
+        .filter(|section| !section.articles.is_empty())
+        .collect();
+
+    let path = export::expand(&output);
+    let title = format!("trss {}", Timestamp::now().strftime("%Y-%m-%d"));
+    epub::write(&title, &sections, &path)?;
+    println!(
+        "Wrote {} articles to {}",
+        sections.iter().map(|s| s.articles.len()).sum::<usize>(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn digest(arguments: &[String]) -> Result<(), Error> {
+    let (mut since, mut format, mut feeds) = ("24h".to_string(), digest::Format::Markdown, vec![]);
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--since" => since = value(&mut arguments, "--since")?,
+            "--format" => {
+                let name = value(&mut arguments, "--format")?;
+                format = digest::Format::parse(&name)
+                    .ok_or_else(|| usage(&format!("unknown format {}, use md or html", name)))?;
+            }
+            "--feed" => feeds.push(value(&mut arguments, "--feed")?),
+            other => return Err(usage(&format!("unexpected argument {}", other))),
+        }
+    }
+    let since = query::duration(&since).map_err(|message| usage(&message))?;
+
+    let config = config::load()?;
+    let state = state::load()?;
+    let now = Timestamp::now();
+    let cutoff = now.checked_sub(since).unwrap_or(Timestamp::MIN);
+    let websites: Vec<Website> = fetch(&config, &feeds)?
+        .into_iter()
+        .map(|mut website| {
+            website.articles.retain(|article| {
+                !state.is_read(article)
+                    && article
+                        .published()
+                        .is_some_and(|published| published >= cutoff)
+            });
+            website
+                .articles
+                .sort_by_key(|article| Reverse(article.published()));
+            website
+        })
+        .filter(|website| !website.articles.is_empty())
+        .collect();
+
+    let title = format!("trss digest {}", now.strftime("%Y-%m-%d"));
+    print!("{}", digest::write(format, &title, &websites));
+    Ok(())
+}
+
+fn serve(arguments: &[String]) -> Result<(), Error> {
+    let mut bind = "127.0.0.1:8080".to_string();
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--bind" => bind = value(&mut arguments, "--bind")?,
+            other => return Err(usage(&format!("unexpected argument {}", other))),
+        }
+    }
+    serve::run(config::load()?, &bind)
+}
+
+/// Fetches the subscriptions a few at a time, only the ones whose URL or name contains one of
+/// `feeds` when there are some. Feeds that fail are reported and left out.
+pub(crate) fn fetch(config: &Config, feeds: &[String]) -> Result<Vec<Website>, Error> {
+    let fetcher = Fetcher::new(&config.fetch)?;
+    let filter = Filter::new(&config.rules)?;
+    let feeds: Vec<String> = feeds.iter().map(|feed| feed.to_lowercase()).collect();
+    let mut subscriptions = config.subscriptions.clone();
+    subscriptions.dedup();
+
+    // URLs are known before fetching, names only after. Values that match no URL are names, and
+    // every subscription has to be fetched to find them.
+    let by_url = |url: &str| feeds.iter().any(|feed| url.to_lowercase().contains(feed));
+    let names: Vec<&String> = feeds
+        .iter()
+        .filter(|feed| {
+            !subscriptions
+                .iter()
+                .any(|url| url.to_lowercase().contains(*feed))
+        })
+        .collect();
+    if !feeds.is_empty() && names.is_empty() {
+        subscriptions.retain(|url| by_url(url));
+    }
+
+    let queue = Mutex::new(subscriptions.iter().enumerate());
+    let mut fetched: Vec<(usize, Website)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..WORKERS.min(subscriptions.len()))
+            .map(|_| {
+                let (queue, fetcher, filter) = (&queue, &fetcher, &filter);
+                scope.spawn(move || {
+                    let mut fetched = vec![];
+                    loop {
+                        let next = match queue.lock() {
+                            Ok(mut queue) => queue.next(),
+                            Err(_) => None,
+                        };
+                        let Some((index, url)) = next else {
+                            return fetched;
+                        };
+                        match example_feed(url, fetcher, filter) {
+                            Ok(website) => fetched.push((index, website)),
+                            Err(error) => eprintln!("Could not fetch {}: {}", url, error),
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+    // in the order of the configuration
+    fetched.sort_by_key(|(index, _)| *index);
+
+    Ok(fetched
+        .into_iter()
+        .map(|(_, website)| website)
+        .filter(|website| {
+            feeds.is_empty()
+                || by_url(&website.url)
+                || names
+                    .iter()
+                    .any(|name| website.name.to_lowercase().contains(*name))
+        })
+        .map(|mut website| {
+            // folders work like tags
+            website.tags = config
+                .folders
+                .iter()
+                .filter(|folder| folder.subscriptions.contains(&website.url))
+                .map(|folder| folder.name.clone())
+                .collect();
+            website
+        })
+        .collect())
+}
@@ -1,12 +1,26 @@
 use crate::error::Error;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-const CONFIG_NAME: &'static str = "trss";
+const CONFIG_NAME: &str = "trss";
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub subscriptions: Vec<String>,
+    pub folders: Vec<Folder>,
+    pub sort: Sort,
+    pub layout: Layout,
+    pub rules: Vec<Rule>,
+    pub virtual_feeds: Vec<VirtualFeed>,
+    pub refresh: Refresh,
+    pub fetch: Fetch,
+    /// Command playing enclosures, `{url}` is replaced by the URL of the file
+    pub player: String,
+    pub downloads: Downloads,
+    pub images: Images,
+    pub save: Save,
 }
 
 /// `Config` implements `Default`
@@ -14,32 +28,303 @@ impl ::std::default::Default for Config {
     fn default() -> Self {
         Self {
             subscriptions: vec![],
+            folders: vec![],
+            sort: Sort::default(),
+            layout: Layout::default(),
+            rules: vec![],
+            virtual_feeds: vec![],
+            refresh: Refresh::default(),
+            fetch: Fetch::default(),
+            player: "mpv {url}".to_string(),
+            downloads: Downloads::default(),
+            images: Images::default(),
+            save: Save::default(),
         }
     }
 }
 
+/// How often the feeds are fetched in the background, feeds asking for a longer delay (RSS
+/// `<ttl>`, `Cache-Control`, ...) are fetched less often
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Refresh {
+    /// Minutes between two fetches of a feed, 0 turns automatic refresh off
+    pub interval: u64,
+    /// Intervals of some subscriptions, by URL
+    pub subscriptions: BTreeMap<String, u64>,
+}
+
+impl ::std::default::Default for Refresh {
+    fn default() -> Self {
+        Self {
+            interval: 30,
+            subscriptions: BTreeMap::new(),
+        }
+    }
+}
+
+/// How feeds are downloaded and what happens to the ones that keep failing
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fetch {
+    /// Seconds before a request is given up
+    pub timeout: u64,
+    /// Attempts after the first one when the network or the server fails
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubled at every attempt with some random jitter
+    pub backoff: u64,
+    /// Days a feed has to fail in a row to be flagged as dead, 0 never flags it
+    pub dead_after_days: u32,
+    /// Stop fetching dead feeds until they are refreshed by hand
+    pub pause_dead: bool,
+    /// Rewrite the subscriptions of the feeds that moved permanently (301 or 308) without asking
+    pub update_moved: bool,
+}
+
+impl ::std::default::Default for Fetch {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            retries: 3,
+            backoff: 500,
+            dead_after_days: 7,
+            pause_dead: false,
+            update_moved: false,
+        }
+    }
+}
+
+/// Where enclosures are downloaded to and which ones are downloaded without asking
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Downloads {
+    pub directory: String,
+    /// Path of a file in `directory`, with `{feed}`, `{title}`, `{date}`, `{episode}` and `{ext}`
+    pub template: String,
+    /// Files downloaded at the same time
+    pub concurrent: usize,
+    /// Newest episodes downloaded as soon as they are fetched, by subscription URL
+    pub auto: BTreeMap<String, usize>,
+}
+
+impl ::std::default::Default for Downloads {
+    fn default() -> Self {
+        let directory = dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+            .join("trss");
+        Self {
+            directory: directory.to_string_lossy().into_owned(),
+            template: "{feed}/{date} {title}.{ext}".to_string(),
+            concurrent: 2,
+            auto: BTreeMap::new(),
+        }
+    }
+}
+
+/// A folder of the sidebar, a subscription can be in several folders which then work like tags
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Folder {
+    pub name: String,
+    /// URLs from `subscriptions`
+    pub subscriptions: Vec<String>,
+}
+
+/// Order of the folders and websites in the sidebar
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    #[default]
+    Name,
+    /// The order of the configuration file
+    Manual,
+    /// Most unread articles first
+    Unread,
+    /// Most recently published article first
+    Updated,
+}
+
+/// How the panes of the main view are arranged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// Panes side by side
+    Horizontal,
+    /// Panes on top of each other
+    Stacked,
+    /// Side by side, stacked when the terminal is narrower than `stack_below`
+    Auto,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    /// Show a live preview of the selected article next to the article list
+    pub preview: bool,
+    pub orientation: Orientation,
+    /// Terminal width (in columns) below which `Auto` stacks the panes
+    pub stack_below: u16,
+    /// Relative sizes of the websites, articles and preview panes
+    pub panes: [u16; 3],
+}
+
+impl ::std::default::Default for Layout {
+    fn default() -> Self {
+        Self {
+            preview: false,
+            orientation: Orientation::Horizontal,
+            stack_below: 120,
+            panes: [20, 30, 50],
+        }
+    }
+}
+
+/// Where articles are saved as Markdown
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Save {
+    pub directory: String,
+    /// Path of a file in `directory`, with `{feed}`, `{title}`, `{date}` and `{ext}`
+    pub template: String,
+}
+
+impl ::std::default::Default for Save {
+    fn default() -> Self {
+        let directory = dirs::document_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+            .join("trss");
+        Self {
+            directory: directory.to_string_lossy().into_owned(),
+            template: "{feed}/{date} {title}.{ext}".to_string(),
+        }
+    }
+}
+
+/// How images are drawn in the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// The best one the terminal is known to support
+    Auto,
+    /// Kitty graphics protocol, also understood by WezTerm and Ghostty
+    Kitty,
+    Sixel,
+    /// Unicode half blocks, two pixels per cell, works everywhere
+    Halfblocks,
+}
+
+/// Images of the articles shown in the reader
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Images {
+    /// When off, images are replaced by a numbered placeholder with their alt text
+    pub enabled: bool,
+    pub protocol: Protocol,
+    /// Lines taken by an image in the reader
+    pub height: u16,
+}
+
+impl ::std::default::Default for Images {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            protocol: Protocol::Auto,
+            height: 12,
+        }
+    }
+}
+
+/// What happens to the articles matching a rule
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Hide,
+    /// Mark the article read
+    Read,
+    /// Highlight the article with `color` in the article list
+    Highlight,
+}
+
+/// A kill-file rule, an article matches when every criterion that is set matches
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    /// Name or URL of the feed
+    pub feed: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub category: Option<String>,
+    /// Description or content of the article
+    pub content: Option<String>,
+    /// Read the criteria above as regular expressions instead of substrings
+    pub regex: bool,
+    /// Only match articles published more than this many days ago
+    pub older_than_days: Option<u32>,
+    pub action: Action,
+    /// Color used by `Action::Highlight`, a name like "yellow" or a hex value like "#ff8800"
+    pub color: Option<String>,
+}
+
+/// A feed in the sidebar listing the articles of every website matching a query, see `query.rs`
+/// for the syntax
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualFeed {
+    pub name: String,
+    pub query: String,
+}
+
 pub(crate) fn store(config: &Config) -> Result<(), Error> {
     confy::store(env!("CARGO_CRATE_NAME"), CONFIG_NAME, config)?;
     Ok(())
 }
 
+/// Loads the configuration, writing the default one when there is none. A file that does not parse
+/// is reported rather than replaced, so a typo never costs the subscriptions.
 pub(crate) fn load() -> Result<Config, Error> {
-    let config = confy::load(env!("CARGO_CRATE_NAME"), CONFIG_NAME);
+    Ok(confy::load(env!("CARGO_CRATE_NAME"), CONFIG_NAME)?)
+}
 
-    match config {
-        Ok(c) => Ok(c),
-        Err(_) => {
-            eprintln!("Error: config not loaded using the default config");
-            let c = Default::default();
-            store(&c)?;
-            Ok(c)
+pub(crate) fn update_or_store(website: String) -> Result<(), Error> {
+    let mut config = load()?;
+    config.subscriptions.push(website);
+
+    store(&config)
+}
+
+/// Points a subscription, in every folder it is in, at the new address of its feed
+pub(crate) fn move_subscription(from: &str, to: &str) -> Result<(), Error> {
+    let mut config = load()?;
+    let folders = config
+        .folders
+        .iter_mut()
+        .flat_map(|folder| folder.subscriptions.iter_mut());
+    for url in config.subscriptions.iter_mut().chain(folders) {
+        if url == from {
+            *url = to.to_string();
         }
     }
+    if let Some(interval) = config.refresh.subscriptions.remove(from) {
+        config
+            .refresh
+            .subscriptions
+            .insert(to.to_string(), interval);
+    }
+
+    store(&config)
 }
 
-pub(crate) fn update_or_store(website: String) -> Result<(), Error> {
+/// Removes a subscription from the configuration and from its folders
+pub(crate) fn remove_subscription(website: &str) -> Result<(), Error> {
     let mut config = load()?;
-    config.subscriptions.push(website);
+    config.subscriptions.retain(|url| url != website);
+    for folder in config.folders.iter_mut() {
+        folder.subscriptions.retain(|url| url != website);
+    }
+    config.refresh.subscriptions.remove(website);
 
     store(&config)
 }
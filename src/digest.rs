@@ -0,0 +1,241 @@
+//! Summaries of the new articles of every feed, in Markdown or HTML, to be mailed or pasted in a
+//! wiki.
+use crate::{
+    export,
+    rss::{Article, Website},
+};
+
+use ego_tree::NodeRef;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use jiff::tz::TimeZone;
+use scraper::{Html, Node, Selector};
+
+/// Longest summary taken from a description without paragraphs
+const MAX_SUMMARY: usize = 400;
+
+/// Formats a digest can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+}
+
+/// The digest of the articles of the websites, grouped by website
+pub(crate) fn write(format: Format, title: &str, websites: &[Website]) -> String {
+    match format {
+        Format::Markdown => markdown(title, websites),
+        Format::Html => html(title, websites),
+    }
+}
+
+fn markdown(title: &str, websites: &[Website]) -> String {
+    let mut text = format!("# {}\n", export::escape(title));
+    if websites.is_empty() {
+        text.push_str("\nNo new articles.\n");
+    }
+    for website in websites {
+        text.push_str(&format!("\n## {}\n", export::escape(&website.name)));
+        for article in &website.articles {
+            let title = export::escape(&article.title);
+            text.push_str(&match link(article) {
+                Some(link) => format!("\n### [{}](<{}>)\n\n", title, link),
+                None => format!("\n### {}\n\n", title),
+            });
+            if let Some(date) = date(article) {
+                text.push_str(&format!("*{}*\n", date));
+            }
+            if let Some(summary) = summary(article) {
+                text.push_str(&format!("\n{}\n", export::escape(&summary)));
+            }
+        }
+    }
+    text
+}
+
+fn html(title: &str, websites: &[Website]) -> String {
+    let mut body = format!("<h1>{}</h1>\n", encode_text(title));
+    if websites.is_empty() {
+        body.push_str("<p>No new articles.</p>\n");
+    }
+    for website in websites {
+        body.push_str(&format!("<h2>{}</h2>\n", encode_text(&website.name)));
+        for article in &website.articles {
+            let title = encode_text(&article.title);
+            body.push_str(&match link(article) {
+                Some(link) => format!(
+                    "<h3><a href=\"{}\">{}</a></h3>\n",
+                    encode_double_quoted_attribute(link),
+                    title
+                ),
+                None => format!("<h3>{}</h3>\n", title),
+            });
+            if let Some(date) = date(article) {
+                body.push_str(&format!("<p><small>{}</small></p>\n", date));
+            }
+            if let Some(summary) = summary(article) {
+                body.push_str(&format!("<p>{}</p>\n", encode_text(&summary)));
+            }
+        }
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        encode_text(title),
+        body
+    )
+}
+
+/// Link of the article when it is a web address, other schemes are no use in a mail
+fn link(article: &Article) -> Option<&str> {
+    article
+        .link
+        .as_deref()
+        .filter(|link| link.starts_with("http://") || link.starts_with("https://"))
+}
+
+fn date(article: &Article) -> Option<String> {
+    article.published().map(|published| {
+        published
+            .to_zoned(TimeZone::system())
+            .strftime("%Y-%m-%d %H:%M")
+            .to_string()
+    })
+}
+
+/// First paragraph of the description, else the whole description cut short. Articles without a
+/// description are summed up by their content the same way.
+fn summary(article: &Article) -> Option<String> {
+    [
+        article.description.as_deref(),
+        Some(article.content.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    // the feed readers stand in for missing content
+    .filter(|html| *html != "N/A")
+    .find_map(first_paragraph)
+}
+
+fn first_paragraph(html: &str) -> Option<String> {
+    let document = Html::parse_fragment(html);
+    let paragraph = Selector::parse("p").expect("valid selector");
+    let first = document
+        .select(&paragraph)
+        .map(|p| collapse(&text(*p)))
+        .find(|text| !text.is_empty());
+    if first.is_some() {
+        return first;
+    }
+
+    let whole = collapse(&text(*document.root_element()));
+    if whole.chars().count() <= MAX_SUMMARY {
+        return Some(whole).filter(|whole| !whole.is_empty());
+    }
+    let cut: String = whole.chars().take(MAX_SUMMARY).collect();
+    let cut = cut
+        .rsplit_once(' ')
+        .map_or(cut.as_str(), |(words, _)| words);
+    Some(format!("{}…", cut.trim_end()))
+}
+
+/// Text of a node, without scripts and with line breaks kept apart
+fn text(node: NodeRef<Node>) -> String {
+    match node.value() {
+        Node::Text(text) => text.to_string(),
+        Node::Element(element) if matches!(element.name(), "script" | "style") => String::new(),
+        Node::Element(element) if element.name() == "br" => " ".to_string(),
+        _ => node.children().map(text).collect(),
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(description: Option<&str>, content: &str) -> Article {
+        Article {
+            title: "Rust & you".to_string(),
+            link: Some("https://example.com/rust?a=1&b=2".to_string()),
+            updated_at: "2024-03-01T12:00:00Z".to_string(),
+            description: description.map(str::to_string),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summaries_are_the_first_paragraph_of_the_description() {
+        let described = article(
+            Some("<p></p><p>First <b>one</b>,\n  on two lines.</p><p>Second.</p>"),
+            "<p>Content.</p>",
+        );
+        assert_eq!(
+            summary(&described).as_deref(),
+            Some("First one, on two lines.")
+        );
+
+        let plain = article(Some("Just text<br>and more <script>x()</script>"), "");
+        assert_eq!(summary(&plain).as_deref(), Some("Just text and more"));
+
+        let long = article(Some(&"word ".repeat(200)), "");
+        let cut = summary(&long).unwrap();
+        assert!(cut.ends_with("word…"));
+        assert!(cut.chars().count() <= MAX_SUMMARY + 1);
+
+        // no description, or an empty one
+        for description in [None, Some(" ")] {
+            let article = article(description, "<div><p>Content.</p></div>");
+            assert_eq!(summary(&article).as_deref(), Some("Content."));
+        }
+        assert_eq!(summary(&article(None, "N/A")), None);
+    }
+
+    #[test]
+    fn digests_group_articles_by_website() {
+        let website = Website {
+            name: "Blog <3".to_string(),
+            articles: vec![article(Some("<p>Hello.</p>"), "")],
+            ..Default::default()
+        };
+        let websites = [website];
+
+        let markdown = write(Format::Markdown, "Digest", &websites);
+        assert!(markdown.starts_with("# Digest\n\n## Blog \\<3\n"));
+        assert!(markdown.contains("### [Rust & you](<https://example.com/rust?a=1&b=2>)\n"));
+        assert!(markdown.contains("\nHello.\n"));
+
+        let html = write(Format::Html, "Digest", &websites);
+        assert!(html.contains("<h2>Blog &lt;3</h2>"));
+        assert!(html.contains(
+            "<h3><a href=\"https://example.com/rust?a=1&amp;b=2\">Rust &amp; you</a></h3>"
+        ));
+        assert!(html.contains("<p>Hello.</p>"));
+
+        assert!(write(Format::Markdown, "Digest", &[]).contains("No new articles."));
+        assert_eq!(Format::parse("MD"), Some(Format::Markdown));
+        assert_eq!(Format::parse("pdf"), None);
+    }
+}
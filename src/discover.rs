@@ -0,0 +1,163 @@
+//! Finds the feeds of a website from the address of one of its pages, the way browsers do: the
+//! `<link rel="alternate">` tags of the page first, then the usual paths of blog engines.
+use crate::rss::{is_feed, Fetcher};
+
+use regex::Regex;
+use reqwest::{header::CONTENT_TYPE, Url};
+use std::error::Error;
+
+/// Types of the `<link>` tags pointing at a feed
+const FEED_TYPES: [&str; 4] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+    "application/json",
+];
+
+/// Paths tried when the page doesn't link to its feed
+const COMMON_PATHS: [&str; 5] = ["/feed", "/rss.xml", "/atom.xml", "/feed.xml", "/index.xml"];
+
+/// A feed found for a website
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub url: String,
+    /// Title of the link, or its type when it has none
+    pub title: String,
+}
+
+/// Feeds found at an address, the address itself when it already is a feed
+pub(crate) fn discover(url: &str, fetcher: &Fetcher) -> Result<Vec<Candidate>, Box<dyn Error>> {
+    let url = &with_scheme(url);
+    let (response, _) = fetcher.follow(url)?;
+    let page = response.url().clone();
+    let html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.contains("html"));
+    let body = response.text()?;
+
+    if !html && !looks_like_html(&body) {
+        return Ok(if is_feed(&body) {
+            vec![Candidate {
+                url: url.to_string(),
+                title: "Feed".to_string(),
+            }]
+        } else {
+            vec![]
+        });
+    }
+
+    let mut candidates = links(&body, &page);
+    if candidates.is_empty() {
+        candidates = COMMON_PATHS
+            .iter()
+            .filter_map(|path| page.join(path).ok())
+            .filter(|probe| {
+                fetcher
+                    .follow(probe.as_str())
+                    .and_then(|(response, _)| Ok(response.text()?))
+                    .is_ok_and(|body| !looks_like_html(&body) && is_feed(&body))
+            })
+            .map(|probe| Candidate {
+                title: probe.path().to_string(),
+                url: probe.to_string(),
+            })
+            .collect();
+    }
+
+    Ok(candidates)
+}
+
+/// Addresses are often typed without a scheme, like `blog.rust-lang.org`
+fn with_scheme(url: &str) -> String {
+    let url = url.trim();
+    if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("https://{}", url)
+    }
+}
+
+/// Some servers send pages without a content type, or as `text/plain`
+fn looks_like_html(body: &str) -> bool {
+    let start: String = body
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .chars()
+        .take(15)
+        .collect::<String>()
+        .to_lowercase();
+
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// Feeds announced by the `<link rel="alternate">` tags of a page, in the order of the page
+fn links(html: &str, page: &Url) -> Vec<Candidate> {
+    let tag = Regex::new(r"(?is)<link\b[^>]*>").unwrap();
+    let attribute =
+        Regex::new(r#"(?is)([a-z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap();
+
+    let mut candidates: Vec<Candidate> = vec![];
+    for link in tag.find_iter(html) {
+        let (mut rel, mut kind, mut href, mut title) = (None, None, None, None);
+        for captures in attribute.captures_iter(link.as_str()) {
+            let value = captures
+                .get(2)
+                .or(captures.get(3))
+                .or(captures.get(4))
+                .map(|value| value.as_str().replace("&amp;", "&"));
+            match captures[1].to_lowercase().as_str() {
+                "rel" => rel = value,
+                "type" => kind = value,
+                "href" => href = value,
+                "title" => title = value,
+                _ => {}
+            }
+        }
+
+        let alternate = rel.is_some_and(|rel| {
+            rel.split_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+        });
+        let Some(kind) = kind.map(|kind| kind.trim().to_lowercase()) else {
+            continue;
+        };
+        if !alternate || !FEED_TYPES.contains(&kind.as_str()) {
+            continue;
+        }
+        let Some(url) = href.and_then(|href| page.join(href.trim()).ok()) else {
+            continue;
+        };
+
+        let url = url.to_string();
+        if candidates.iter().all(|candidate| candidate.url != url) {
+            candidates.push(Candidate {
+                title: title
+                    .filter(|title| !title.trim().is_empty())
+                    .unwrap_or(kind),
+                url,
+            });
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_https_to_bare_domains() {
+        assert_eq!(
+            with_scheme("blog.rust-lang.org"),
+            "https://blog.rust-lang.org"
+        );
+        assert_eq!(
+            with_scheme(" localhost:8080/feed "),
+            "https://localhost:8080/feed"
+        );
+        assert_eq!(with_scheme("http://example.com"), "http://example.com");
+    }
+}
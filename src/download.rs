@@ -0,0 +1,609 @@
+//! Downloads enclosures in the background so podcasts can be listened to offline.
+//!
+//! A few worker threads, as many as `concurrent` in the configuration, take the queued files one
+//! after the other. Files are written next to their final path with a `.part` extension, an
+//! interrupted download is resumed from there with an HTTP `Range` request. A `.part.origin` file
+//! keeps the URL and the `ETag` or `Last-Modified` of the partial file, the range is asked with
+//! `If-Range` so a file that changed on the server is downloaded whole again.
+use crate::{
+    config,
+    enclosure::{self, Enclosure},
+    error::Error,
+    export,
+    rss::Article,
+};
+
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    StatusCode, Url,
+};
+use std::{
+    error,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a worker reports the progress of a download
+const PROGRESS_EVERY: Duration = Duration::from_millis(250);
+
+/// A file to download
+struct Job {
+    url: String,
+    path: PathBuf,
+}
+
+/// Sent by the workers
+enum Event {
+    Started(String),
+    Progress {
+        url: String,
+        received: u64,
+        total: Option<u64>,
+    },
+    Finished(String),
+    Failed {
+        url: String,
+        error: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Status {
+    Queued,
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// A download of the downloads view
+#[derive(Clone, Debug)]
+pub(crate) struct Download {
+    pub url: String,
+    pub path: PathBuf,
+    pub received: u64,
+    pub total: Option<u64>,
+    pub status: Status,
+}
+
+impl Download {
+    /// Name of the file and how much of it arrived, like `episode.mp3 (3.1 MB / 24.3 MB)`
+    pub fn label(&self) -> String {
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.url.clone());
+        match (&self.status, self.total) {
+            (Status::Failed(error), _) => format!("{}: {}", name, error),
+            (Status::Queued, _) => format!("{} (queued)", name),
+            (_, Some(total)) => format!(
+                "{} ({} / {})",
+                name,
+                enclosure::size(self.received),
+                enclosure::size(total)
+            ),
+            (_, None) => format!("{} ({})", name, enclosure::size(self.received)),
+        }
+    }
+
+    pub fn ratio(&self) -> f64 {
+        match (&self.status, self.total) {
+            (Status::Done, _) => 1.0,
+            (_, Some(total)) if total > 0 => (self.received as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Handle on the download workers, also keeps the list of the downloads of this run
+pub(crate) struct Downloader {
+    jobs: Sender<Job>,
+    events: Receiver<Event>,
+    config: config::Downloads,
+    pub downloads: Vec<Download>,
+}
+
+impl Downloader {
+    /// Starts the workers
+    pub fn spawn(config: config::Downloads) -> Result<Downloader, Error> {
+        // no overall timeout, an episode takes a while on a slow connection
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(30))
+            .timeout(None)
+            .build()?;
+
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let (sender, events) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..config.concurrent.max(1) {
+            let (client, receiver, sender) = (client.clone(), receiver.clone(), sender.clone());
+            thread::spawn(move || loop {
+                // the lock is only held while waiting for the next job
+                let job = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                let Ok(job) = job else {
+                    return;
+                };
+
+                let _ = sender.send(Event::Started(job.url.clone()));
+                let event = match download(&client, &job, &sender) {
+                    Ok(()) => Event::Finished(job.url),
+                    Err(error) => Event::Failed {
+                        url: job.url,
+                        error: error.to_string(),
+                    },
+                };
+                if sender.send(event).is_err() {
+                    return;
+                }
+            });
+        }
+
+        Ok(Downloader {
+            jobs,
+            events,
+            config,
+            downloads: vec![],
+        })
+    }
+
+    /// Queues an enclosure of an article, or again when it failed, returns `false` when it is
+    /// already queued, running or done
+    pub fn queue(&mut self, article: &Article, enclosure: &Enclosure) -> bool {
+        if let Some(download) = self.downloads.iter_mut().find(|d| d.url == enclosure.url) {
+            if !matches!(download.status, Status::Failed(_)) {
+                return false;
+            }
+            // a failed download starts again where its partial file stopped
+            download.status = Status::Queued;
+            let _ = self.jobs.send(Job {
+                url: download.url.clone(),
+                path: download.path.clone(),
+            });
+            return true;
+        }
+
+        // another enclosure with the same name gets a numbered file rather than sharing one
+        let path = path(&self.config, article, enclosure);
+        let path = (1..)
+            .map(|number| export::numbered(&path, number))
+            .find(|path| {
+                !path.exists()
+                    && self.downloads.iter().all(|download| download.path != *path)
+                    && origin_url(path).is_none_or(|url| url == enclosure.url)
+            })
+            .unwrap_or(path);
+        let _ = self.jobs.send(Job {
+            url: enclosure.url.clone(),
+            path: path.clone(),
+        });
+        self.downloads.push(Download {
+            url: enclosure.url.clone(),
+            path,
+            received: 0,
+            total: enclosure.length,
+            status: Status::Queued,
+        });
+        true
+    }
+
+    /// Applies what the workers reported, returns the downloads that finished since the last
+    /// call
+    pub fn update(&mut self) -> Vec<Download> {
+        let mut finished = vec![];
+        while let Ok(event) = self.events.try_recv() {
+            let url = match &event {
+                Event::Started(url)
+                | Event::Progress { url, .. }
+                | Event::Finished(url)
+                | Event::Failed { url, .. } => url.clone(),
+            };
+            let Some(download) = self.downloads.iter_mut().find(|d| d.url == url) else {
+                continue;
+            };
+
+            match event {
+                Event::Started(_) => download.status = Status::Running,
+                Event::Progress {
+                    received, total, ..
+                } => {
+                    download.received = received;
+                    download.total = total.or(download.total);
+                }
+                Event::Finished(_) => {
+                    download.status = Status::Done;
+                    finished.push(download.clone());
+                }
+                Event::Failed { error, .. } => download.status = Status::Failed(error),
+            }
+        }
+        finished
+    }
+
+    /// Downloads waiting or running
+    pub fn active(&self) -> usize {
+        self.downloads
+            .iter()
+            .filter(|d| matches!(d.status, Status::Queued | Status::Running))
+            .count()
+    }
+
+    /// How many of the newest episodes of a subscription are downloaded without asking
+    pub fn auto(&self, url: &str) -> usize {
+        self.config.auto.get(url).copied().unwrap_or(0)
+    }
+}
+
+/// Where an enclosure is saved, from the filename template of the configuration
+fn path(config: &config::Downloads, article: &Article, enclosure: &Enclosure) -> PathBuf {
+    export::file_path(
+        &config.directory,
+        &config.template,
+        article,
+        &extension(enclosure),
+    )
+}
+
+/// Extension of the file in the URL, or one matching the MIME type
+fn extension(enclosure: &Enclosure) -> String {
+    let from_url = Url::parse(&enclosure.url).ok().and_then(|url| {
+        let name = url.path_segments()?.next_back()?.to_string();
+        let (_, extension) = name.rsplit_once('.')?;
+        (!extension.is_empty()
+            && extension.len() <= 5
+            && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .then(|| extension.to_lowercase())
+    });
+
+    from_url.unwrap_or_else(|| {
+        match enclosure.mime_type.as_str() {
+            "audio/mpeg" | "audio/mp3" => "mp3",
+            "audio/mp4" | "audio/x-m4a" | "audio/m4a" => "m4a",
+            "audio/ogg" | "application/ogg" => "ogg",
+            "audio/opus" => "opus",
+            "audio/aac" => "aac",
+            "audio/flac" => "flac",
+            "video/mp4" => "mp4",
+            "video/webm" => "webm",
+            "application/pdf" => "pdf",
+            _ => "bin",
+        }
+        .to_string()
+    })
+}
+
+/// Where a download is written until it is complete
+fn partial(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Where the URL and the validator of a partial file are kept
+fn origin(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part.origin");
+    path.with_file_name(name)
+}
+
+/// URL the partial file of a path was downloaded from, if there is one
+fn origin_url(path: &Path) -> Option<String> {
+    let text = fs::read_to_string(origin(path)).ok()?;
+    Some(text.lines().next().unwrap_or_default().to_string())
+}
+
+/// Validator to resume the partial file with, only when it was downloaded from the same URL
+fn resumable(origin: &str, url: &str) -> Option<String> {
+    let (from, validator) = origin.split_once('\n')?;
+    let validator = validator.trim();
+    (from == url && !validator.is_empty()).then(|| validator.to_string())
+}
+
+/// What tells whether the file changed on the server: a strong `ETag`, else `Last-Modified`. Weak
+/// tags cannot be used with `If-Range`.
+fn validator(headers: &HeaderMap) -> Option<String> {
+    let etag = headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"));
+    etag.or_else(|| headers.get(LAST_MODIFIED)?.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Downloads a file, resuming the partial file of an earlier attempt at the same URL. A file that
+/// is already there is not replaced.
+fn download(
+    client: &Client,
+    job: &Job,
+    sender: &Sender<Event>,
+) -> Result<(), Box<dyn error::Error>> {
+    if job.path.exists() {
+        return Err(format!("{} already exists", job.path.display()).into());
+    }
+    if let Some(parent) = job.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let partial = partial(&job.path);
+    let origin = origin(&job.path);
+    let resume = fs::read_to_string(&origin)
+        .ok()
+        .and_then(|origin| resumable(&origin, &job.url))
+        .map(|validator| {
+            (
+                fs::metadata(&partial).map(|m| m.len()).unwrap_or(0),
+                validator,
+            )
+        })
+        .filter(|(offset, _)| *offset > 0);
+    let mut request = client.get(&job.url);
+    if let Some((offset, validator)) = &resume {
+        request = request
+            .header(RANGE, format!("bytes={}-", offset))
+            .header(IF_RANGE, validator);
+    }
+    let mut response = request.send()?;
+    let offset = resume.map_or(0, |(offset, _)| offset);
+
+    // the partial file was complete, only the rename was missing
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        fs::rename(&partial, &job.path)?;
+        let _ = fs::remove_file(&origin);
+        return Ok(());
+    }
+    response.error_for_status_ref()?;
+
+    // servers ignoring the range, or whose file changed, send the whole file again
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT
+        && response.headers().contains_key(CONTENT_RANGE);
+    let (mut file, mut received) = if resumed {
+        (OpenOptions::new().append(true).open(&partial)?, offset)
+    } else {
+        let validator = validator(response.headers()).unwrap_or_default();
+        fs::write(&origin, format!("{}\n{}\n", job.url, validator))?;
+        (File::create(&partial)?, 0)
+    };
+    let total = response.content_length().map(|length| length + received);
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut reported = Instant::now();
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])?;
+        received += read as u64;
+
+        if reported.elapsed() >= PROGRESS_EVERY {
+            reported = Instant::now();
+            let _ = sender.send(Event::Progress {
+                url: job.url.clone(),
+                received,
+                total,
+            });
+        }
+    }
+    file.flush()?;
+
+    if total.is_some_and(|total| received < total) {
+        return Err(format!("the connection closed after {} bytes", received).into());
+    }
+    fs::rename(&partial, &job.path)?;
+    let _ = fs::remove_file(&origin);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, env};
+    use tiny_http::{Header, Response, Server};
+
+    fn directory(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("trss-{}-{}", name, std::process::id()))
+    }
+
+    fn config(directory: &Path) -> config::Downloads {
+        config::Downloads {
+            directory: directory.to_string_lossy().into_owned(),
+            template: "{feed}/{date} {title}.{ext}".to_string(),
+            concurrent: 1,
+            auto: BTreeMap::new(),
+        }
+    }
+
+    fn enclosure(url: &str, mime_type: &str) -> Enclosure {
+        Enclosure {
+            url: url.to_string(),
+            mime_type: mime_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn article() -> Article {
+        Article {
+            feed: "Show: the \"podcast\"".to_string(),
+            title: "Episode 1/2?".to_string(),
+            updated_at: "2024-03-01T12:00:00Z".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn paths_follow_the_template() {
+        let config = config(Path::new("/downloads"));
+        let file = path(
+            &config,
+            &article(),
+            &enclosure("https://a.example/e.mp3", ""),
+        );
+        // the date is local, the first of March or the second
+        let date = &file.file_name().unwrap().to_string_lossy()[..10];
+        assert!(date.starts_with("2024-03-0"));
+        assert_eq!(
+            file,
+            Path::new("/downloads/Show_ the _podcast_").join(format!("{} Episode 1_2_.mp3", date))
+        );
+
+        let undated = Article {
+            updated_at: String::new(),
+            ..article()
+        };
+        let file = path(&config, &undated, &enclosure("https://a.example/e.mp3", ""));
+        assert!(file.ends_with("undated Episode 1_2_.mp3"));
+    }
+
+    #[test]
+    fn extensions_come_from_the_url_or_the_type() {
+        let extension = |url, mime_type| extension(&enclosure(url, mime_type));
+        assert_eq!(extension("https://a.example/e.MP3?x=1", "audio/ogg"), "mp3");
+        assert_eq!(extension("https://a.example/e", "audio/x-m4a"), "m4a");
+        assert_eq!(
+            extension("https://a.example/e.download-me", "audio/mpeg"),
+            "mp3"
+        );
+        assert_eq!(extension("https://a.example/e.", "text/plain"), "bin");
+    }
+
+    #[test]
+    fn partial_files_are_resumed_from_the_same_url() {
+        assert_eq!(
+            resumable(
+                "https://a.example/e.mp3\n\"v1\"\n",
+                "https://a.example/e.mp3"
+            ),
+            Some("\"v1\"".to_string())
+        );
+        assert_eq!(
+            resumable(
+                "https://a.example/e.mp3\n\"v1\"\n",
+                "https://b.example/e.mp3"
+            ),
+            None
+        );
+        assert_eq!(
+            resumable("https://a.example/e.mp3\n\n", "https://a.example/e.mp3"),
+            None
+        );
+    }
+
+    #[test]
+    fn weak_tags_are_not_validators() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            LAST_MODIFIED,
+            "Fri, 01 Mar 2024 12:00:00 GMT".parse().unwrap(),
+        );
+        headers.insert(ETAG, "W/\"v1\"".parse().unwrap());
+        assert_eq!(
+            validator(&headers).as_deref(),
+            Some("Fri, 01 Mar 2024 12:00:00 GMT")
+        );
+        headers.insert(ETAG, "\"v1\"".parse().unwrap());
+        assert_eq!(validator(&headers).as_deref(), Some("\"v1\""));
+    }
+
+    #[test]
+    fn failed_downloads_are_queued_again_and_names_are_shared_apart() {
+        let directory = directory("queue");
+        let (jobs, queued) = mpsc::channel();
+        let (_sender, events) = mpsc::channel();
+        let mut downloader = Downloader {
+            jobs,
+            events,
+            config: config(&directory),
+            downloads: vec![],
+        };
+        let first = enclosure("https://a.example/one.mp3", "");
+        let second = enclosure("https://a.example/two.mp3", "");
+
+        assert!(downloader.queue(&article(), &first));
+        assert!(downloader.queue(&article(), &second));
+        assert!(!downloader.queue(&article(), &first));
+        let paths: Vec<&Path> = downloader
+            .downloads
+            .iter()
+            .map(|d| d.path.as_path())
+            .collect();
+        assert_eq!(paths[1], export::numbered(paths[0], 2));
+
+        downloader.downloads[0].status = Status::Failed("timed out".to_string());
+        assert!(downloader.queue(&article(), &first));
+        assert_eq!(downloader.downloads[0].status, Status::Queued);
+        let urls: Vec<String> = queued.try_iter().map(|job| job.url).collect();
+        assert_eq!(urls, [&*first.url, &*second.url, &*first.url]);
+    }
+
+    /// Answers one request for `abcdefghij`, or with `56789` from byte 5 when the range is asked
+    /// for `etag`, so a resumed file reads `0123456789`
+    fn server(etag: &'static str) -> (String, thread::JoinHandle<()>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/e.mp3", server.server_addr().to_ip().unwrap());
+        let handle = thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let header = |name: &'static str| {
+                request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv(name))
+                    .map(|header| header.value.to_string())
+            };
+            let ranged = header("Range").as_deref() == Some("bytes=5-")
+                && header("If-Range").as_deref() == Some(etag);
+            let response = if ranged {
+                Response::from_string("56789")
+                    .with_status_code(206)
+                    .with_header(Header::from_bytes("Content-Range", "bytes 5-9/10").unwrap())
+            } else {
+                Response::from_string("abcdefghij")
+            };
+            let response = response.with_header(Header::from_bytes("ETag", etag).unwrap());
+            request.respond(response).unwrap();
+        });
+        (url, handle)
+    }
+
+    fn resume(name: &str, etag: &'static str) -> String {
+        let directory = directory(name);
+        fs::create_dir_all(&directory).unwrap();
+        let (url, server) = server(etag);
+        let job = Job {
+            url: url.clone(),
+            path: directory.join("e.mp3"),
+        };
+        fs::write(partial(&job.path), "01234").unwrap();
+        fs::write(origin(&job.path), format!("{}\n\"v1\"\n", url)).unwrap();
+
+        let client = Client::builder().no_proxy().build().unwrap();
+        let (sender, _events) = mpsc::channel();
+        download(&client, &job, &sender).unwrap();
+        server.join().unwrap();
+
+        let downloaded = fs::read_to_string(&job.path).unwrap();
+        assert!(!origin(&job.path).exists());
+        let _ = fs::remove_dir_all(&directory);
+        downloaded
+    }
+
+    #[test]
+    fn unchanged_files_are_resumed() {
+        assert_eq!(resume("resumed", "\"v1\""), "0123456789");
+    }
+
+    #[test]
+    fn changed_files_are_downloaded_whole() {
+        assert_eq!(resume("changed", "\"v2\""), "abcdefghij");
+    }
+}
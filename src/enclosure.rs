@@ -0,0 +1,210 @@
+//! Files attached to articles: RSS `<enclosure>`, Atom `rel="enclosure"` links, Media RSS and
+//! JSON Feed attachments, usually the audio or video of a podcast episode.
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    process::{Command, Stdio},
+    thread,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Enclosure {
+    pub url: String,
+    pub mime_type: String,
+    /// Size in bytes
+    pub length: Option<u64>,
+    /// Duration in seconds
+    pub duration: Option<u64>,
+}
+
+impl Enclosure {
+    /// One line description for the article header, like `audio/mpeg, 24.3 MB, 45:12`
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        if !self.mime_type.is_empty() {
+            parts.push(self.mime_type.clone());
+        }
+        if let Some(length) = self.length.filter(|length| *length > 0) {
+            parts.push(size(length));
+        }
+        if let Some(duration) = self.duration {
+            parts.push(clock(duration));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Reads `itunes:duration`, which is either seconds or `[HH:]MM:SS`
+pub(crate) fn duration(text: &str) -> Option<u64> {
+    text.trim().split(':').try_fold(0, |seconds, part| {
+        let part: u64 = part.trim().split('.').next()?.parse().ok()?;
+        Some(seconds * 60 + part)
+    })
+}
+
+/// Media RSS `<media:content>` elements, alone or in a `<media:group>`, as their attributes
+pub(crate) fn media(
+    contents: impl Iterator<Item = BTreeMap<String, String>>,
+) -> impl Iterator<Item = Enclosure> {
+    contents.filter_map(|attributes| {
+        Some(Enclosure {
+            url: attributes.get("url")?.clone(),
+            mime_type: attributes.get("type").cloned().unwrap_or_default(),
+            length: attributes
+                .get("fileSize")
+                .and_then(|size| size.parse().ok()),
+            duration: attributes.get("duration").and_then(|d| duration(d)),
+        })
+    })
+}
+
+/// Size in bytes for people, like `24.3 MB`
+pub(crate) fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next;
+    }
+    if unit == "B" {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, unit)
+    }
+}
+
+fn clock(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Splits a command into words the way a shell does: single quotes keep everything, double
+/// quotes and backslashes escape the next character
+fn words(command: &str) -> io::Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (Some('"') | None, '\\') => {
+                let escaped = chars.next().unwrap_or('\\');
+                let word = word.get_or_insert_with(String::new);
+                // in double quotes only a few characters can be escaped
+                if quote.is_some() && !matches!(escaped, '"' | '\\' | '$' | '`') {
+                    word.push('\\');
+                }
+                word.push(escaped);
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unterminated quote in the player",
+        ));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Starts the player of the configuration, `{url}` in the command is replaced by the URL of the
+/// enclosure. The player runs on its own, trss doesn't wait for it.
+pub(crate) fn play(player: &str, enclosure: &Enclosure) -> io::Result<()> {
+    let mut words = words(player)?
+        .into_iter()
+        .map(|word| word.replace("{url}", &enclosure.url));
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no player configured"))?;
+
+    let mut command = Command::new(program);
+    command.args(words);
+    if !player.contains("{url}") {
+        command.arg(&enclosure.url);
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // reaped once it exits, so that it doesn't linger as a zombie
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_seconds_or_clock_times() {
+        assert_eq!(duration("2712"), Some(2712));
+        assert_eq!(duration("45:12"), Some(45 * 60 + 12));
+        assert_eq!(duration(" 1:02:03.500 "), Some(3723));
+        assert_eq!(duration(""), None);
+        assert_eq!(duration("1:xx"), None);
+        assert_eq!(duration("-5"), None);
+    }
+
+    #[test]
+    fn sizes_and_clocks_for_people() {
+        assert_eq!(size(999), "999 B");
+        assert_eq!(size(24_300_000), "24.3 MB");
+        assert_eq!(size(1_500_000_000_000), "1.5 TB");
+        assert_eq!(clock(59), "0:59");
+        assert_eq!(clock(45 * 60 + 12), "45:12");
+        assert_eq!(clock(3723), "1:02:03");
+        let enclosure = Enclosure {
+            mime_type: "audio/mpeg".to_string(),
+            length: Some(24_300_000),
+            duration: Some(2712),
+            ..Default::default()
+        };
+        assert_eq!(enclosure.describe(), "audio/mpeg, 24.3 MB, 45:12");
+    }
+
+    #[test]
+    fn players_are_split_like_a_shell_does() {
+        assert_eq!(
+            words("mpv --title='My show' {url}").unwrap(),
+            ["mpv", "--title=My show", "{url}"]
+        );
+        assert_eq!(
+            words(r#"  "/opt/my player/bin" --say "a \"quoted\" \w" '' x\ y "#).unwrap(),
+            ["/opt/my player/bin", "--say", "a \"quoted\" \\w", "", "x y"]
+        );
+        assert!(words("mpv 'unterminated").is_err());
+        assert!(words("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn urls_are_passed_as_one_argument() {
+        let enclosure = Enclosure {
+            url: "https://example.com/a b.mp3".to_string(),
+            ..Default::default()
+        };
+        assert!(play("true --title='My show' {url}", &enclosure).is_ok());
+        assert!(play("", &enclosure).is_err());
+        assert!(play("'true", &enclosure).is_err());
+    }
+}
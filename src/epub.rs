@@ -0,0 +1,681 @@
+//! Packs articles into an EPUB 3 book for e-readers, with a table of contents per feed and the
+//! images already downloaded for the reader.
+//!
+//! E-readers expect XHTML, the HTML of the articles is parsed and written out again keeping only
+//! the elements and attributes books commonly use.
+use crate::{error::Error, images, rss::Article};
+
+use ego_tree::NodeRef;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use jiff::Timestamp;
+use scraper::{Html, Node};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Elements kept as they are
+const ELEMENTS: [&str; 44] = [
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "ins",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Elements dropped with everything inside them
+const DROPPED: [&str; 10] = [
+    "script", "style", "iframe", "object", "embed", "form", "noscript", "video", "audio", "svg",
+];
+
+const VOID: [&str; 3] = ["br", "hr", "img"];
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+const STYLE: &str = "body { font-family: serif; line-height: 1.4; }
+.meta { color: #555; font-size: 0.9em; }
+img { max-width: 100%; height: auto; }
+pre { white-space: pre-wrap; }
+";
+
+/// The articles of a feed, a section of the table of contents
+pub(crate) struct Section {
+    pub feed: String,
+    pub articles: Vec<Article>,
+}
+
+/// Groups articles by feed, in the order the feeds first appear
+pub(crate) fn sections(articles: &[Article]) -> Vec<Section> {
+    let mut sections: Vec<Section> = vec![];
+    for article in articles {
+        match sections.iter_mut().find(|s| s.feed == article.feed) {
+            Some(section) => section.articles.push(article.clone()),
+            None => sections.push(Section {
+                feed: article.feed.clone(),
+                articles: vec![article.clone()],
+            }),
+        }
+    }
+    sections
+}
+
+/// An image of the book
+struct Picture {
+    file: String,
+    media_type: String,
+    bytes: Vec<u8>,
+}
+
+/// Images found while converting the articles, by URL. `None` for the ones not in the cache.
+#[derive(Default)]
+struct Pictures(HashMap<String, Option<Picture>>);
+
+impl Pictures {
+    /// Path of an image inside the book, when it was cached
+    fn add(&mut self, src: &str) -> Option<String> {
+        let picture = self.0.entry(src.to_string()).or_insert_with(|| {
+            let bytes = images::cached(src)?;
+            let format = image::guess_format(&bytes).ok()?;
+            let extension = format.extensions_str().first()?;
+            Some(Picture {
+                file: format!("images/{}.{}", images::cache_name(src), extension),
+                media_type: format.to_mime_type().to_string(),
+                bytes,
+            })
+        });
+        picture.as_ref().map(|picture| picture.file.clone())
+    }
+
+    fn all(&self) -> impl Iterator<Item = &Picture> {
+        self.0.values().flatten()
+    }
+}
+
+/// Writes the book
+pub(crate) fn write(title: &str, sections: &[Section], path: &Path) -> Result<(), Error> {
+    if sections.iter().all(|section| section.articles.is_empty()) {
+        return Err(Error::ExportError(
+            "there are no articles to export".to_string(),
+        ));
+    }
+
+    // the book is written next to its path and renamed once complete, a failure leaves no
+    // broken book behind
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let written = File::create(&partial)
+        .map_err(Error::from)
+        .and_then(|file| pack(title, sections, file))
+        .and_then(|()| Ok(fs::rename(&partial, path)?));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+fn pack(title: &str, sections: &[Section], file: File) -> Result<(), Error> {
+    let now = Timestamp::now();
+    let identifier = format!("urn:{}:{}", env!("CARGO_CRATE_NAME"), now.as_millisecond());
+    let language = language(sections);
+
+    let mut pictures = Pictures::default();
+    let chapters: Vec<String> = sections
+        .iter()
+        .flat_map(|section| section.articles.iter())
+        .map(|article| chapter(article, &mut pictures))
+        .collect();
+
+    let mut zip = ZipWriter::new(file);
+    // the mimetype comes first and uncompressed so the file can be recognized
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, bytes: &[u8], options| -> Result<(), Error> {
+        zip.start_file(name, options).map_err(zip_error)?;
+        zip.write_all(bytes)?;
+        Ok(())
+    };
+
+    add("mimetype", b"application/epub+zip", stored)?;
+    add("META-INF/container.xml", CONTAINER.as_bytes(), deflated)?;
+    add("OEBPS/style.css", STYLE.as_bytes(), deflated)?;
+    add(
+        "OEBPS/content.opf",
+        package(
+            title,
+            &identifier,
+            &language,
+            now,
+            chapters.len(),
+            &pictures,
+        )
+        .as_bytes(),
+        deflated,
+    )?;
+    add(
+        "OEBPS/nav.xhtml",
+        navigation(title, &language, sections).as_bytes(),
+        deflated,
+    )?;
+    add(
+        "OEBPS/toc.ncx",
+        ncx(title, &identifier, sections).as_bytes(),
+        deflated,
+    )?;
+    for (index, chapter) in chapters.iter().enumerate() {
+        add(
+            &format!("OEBPS/{}", chapter_file(index)),
+            chapter.as_bytes(),
+            deflated,
+        )?;
+    }
+    for picture in pictures.all() {
+        add(&format!("OEBPS/{}", picture.file), &picture.bytes, deflated)?;
+    }
+
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+/// Text escaped for XML, without the control characters XML 1.0 does not allow, which feeds
+/// decoded from windows-1252 often carry
+fn escape_text(text: &str) -> String {
+    encode_text(&allowed(text)).into_owned()
+}
+
+fn escape_attribute(value: &str) -> String {
+    encode_double_quoted_attribute(&allowed(value)).into_owned()
+}
+
+fn allowed(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect()
+}
+
+fn zip_error(error: zip::result::ZipError) -> Error {
+    match error {
+        zip::result::ZipError::Io(error) => Error::WriteError(error),
+        error => Error::WriteError(io::Error::other(error)),
+    }
+}
+
+fn chapter_file(index: usize) -> String {
+    format!("text/article{}.xhtml", index + 1)
+}
+
+/// The language most articles are in, `und` (undetermined) when no feed says
+fn language(sections: &[Section]) -> String {
+    let mut counts: Vec<(&str, usize)> = vec![];
+    let languages = sections
+        .iter()
+        .flat_map(|section| section.articles.iter())
+        .filter_map(|article| article.language.as_deref());
+    for language in languages {
+        match counts.iter_mut().find(|(counted, _)| *counted == language) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((language, 1)),
+        }
+    }
+    // the first of the most common
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map_or_else(|| "und".to_string(), |(language, _)| language.to_string())
+}
+
+fn xhtml(title: &str, language: Option<&str>, head: &str, body: &str) -> String {
+    let language = language
+        .map(|language| {
+            let language = escape_attribute(language);
+            format!(" xml:lang=\"{}\" lang=\"{}\"", language, language)
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"{}>
+<head>
+<title>{}</title>
+{}</head>
+<body>
+{}
+</body>
+</html>
+"#,
+        language,
+        escape_text(title),
+        head,
+        body
+    )
+}
+
+/// An article with its title, feed, author, date and link above the content
+fn chapter(article: &Article, pictures: &mut Pictures) -> String {
+    let mut about = vec![escape_text(&article.feed)];
+    if !article.author.is_empty() {
+        about.push(escape_text(&article.author));
+    }
+    if let Some(published) = article.published() {
+        about.push(published.strftime("%Y-%m-%d").to_string());
+    }
+
+    let mut body = format!(
+        "<h1>{}</h1>\n<p class=\"meta\">{}</p>\n",
+        escape_text(&article.title),
+        about.join(" · ")
+    );
+    if let Some(link) = &article.link {
+        body.push_str(&format!(
+            "<p class=\"meta\"><a href=\"{}\">{}</a></p>\n",
+            escape_attribute(link),
+            escape_text(link)
+        ));
+    }
+    body.push_str(&content(&article.content, pictures));
+
+    xhtml(
+        &article.title,
+        article.language.as_deref(),
+        r#"<link rel="stylesheet" type="text/css" href="../style.css"/>
+"#,
+        &body,
+    )
+}
+
+/// The HTML of an article as XHTML
+fn content(html: &str, pictures: &mut Pictures) -> String {
+    let fragment = Html::parse_fragment(html);
+    let mut xhtml = String::new();
+    for child in fragment.root_element().children() {
+        convert(child, pictures, &mut xhtml);
+    }
+    xhtml
+}
+
+fn convert(node: NodeRef<Node>, pictures: &mut Pictures, out: &mut String) {
+    match node.value() {
+        Node::Text(text) => out.push_str(&escape_text(text)),
+        Node::Element(element) => {
+            let name = element.name();
+            if DROPPED.contains(&name) {
+                return;
+            }
+            if name == "img" {
+                let alt = element.attr("alt").unwrap_or_default();
+                match element.attr("src").and_then(|src| pictures.add(src)) {
+                    Some(file) => out.push_str(&format!(
+                        "<img src=\"../{}\" alt=\"{}\"/>",
+                        file,
+                        escape_attribute(alt)
+                    )),
+                    // images that were never shown in the reader are not in the cache
+                    None if !alt.trim().is_empty() => {
+                        out.push_str(&format!("[{}]", escape_text(alt.trim())))
+                    }
+                    None => {}
+                }
+                return;
+            }
+            // unknown elements are unwrapped
+            if !ELEMENTS.contains(&name) {
+                for child in node.children() {
+                    convert(child, pictures, out);
+                }
+                return;
+            }
+
+            out.push('<');
+            out.push_str(name);
+            let attributes: &[&str] = match name {
+                "a" => &["href", "title"],
+                "td" | "th" => &["colspan", "rowspan"],
+                "abbr" => &["title"],
+                _ => &[],
+            };
+            for attribute in attributes {
+                let Some(value) = element.attr(attribute) else {
+                    continue;
+                };
+                // only links leaving the book
+                if *attribute == "href"
+                    && !["http://", "https://", "mailto:"]
+                        .iter()
+                        .any(|scheme| value.starts_with(scheme))
+                {
+                    continue;
+                }
+                out.push_str(&format!(" {}=\"{}\"", attribute, escape_attribute(value)));
+            }
+
+            if VOID.contains(&name) {
+                out.push_str("/>");
+                return;
+            }
+            out.push('>');
+            for child in node.children() {
+                convert(child, pictures, out);
+            }
+            out.push_str(&format!("</{}>", name));
+        }
+        _ => {}
+    }
+}
+
+fn package(
+    title: &str,
+    identifier: &str,
+    language: &str,
+    now: Timestamp,
+    chapters: usize,
+    pictures: &Pictures,
+) -> String {
+    let mut manifest = String::from(
+        r#"    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="style" href="style.css" media-type="text/css"/>
+"#,
+    );
+    let mut spine = String::new();
+    for index in 0..chapters {
+        manifest.push_str(&format!(
+            "    <item id=\"article{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            index + 1,
+            chapter_file(index)
+        ));
+        spine.push_str(&format!("    <itemref idref=\"article{}\"/>\n", index + 1));
+    }
+    for (index, picture) in pictures.all().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"image{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1,
+            picture.file,
+            picture.media_type
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="identifier">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="identifier">{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:language>{}</dc:language>
+    <dc:creator>{}</dc:creator>
+    <dc:date>{}</dc:date>
+    <meta property="dcterms:modified">{}</meta>
+  </metadata>
+  <manifest>
+{}  </manifest>
+  <spine toc="ncx">
+{}  </spine>
+</package>
+"#,
+        identifier,
+        escape_text(title),
+        escape_text(language),
+        env!("CARGO_CRATE_NAME"),
+        now.strftime("%Y-%m-%d"),
+        now.strftime("%Y-%m-%dT%H:%M:%SZ"),
+        manifest,
+        spine
+    )
+}
+
+/// The EPUB 3 table of contents, a list of feeds with their articles
+fn navigation(title: &str, language: &str, sections: &[Section]) -> String {
+    let mut list = String::from("<ol>\n");
+    let mut index = 0;
+    for section in sections {
+        list.push_str(&format!(
+            "<li><span>{}</span>\n<ol>\n",
+            escape_text(&section.feed)
+        ));
+        for article in &section.articles {
+            list.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                chapter_file(index),
+                escape_text(&article.title)
+            ));
+            index += 1;
+        }
+        list.push_str("</ol>\n</li>\n");
+    }
+    list.push_str("</ol>");
+
+    xhtml(
+        title,
+        Some(language),
+        "",
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n{}\n</nav>",
+            escape_text(title),
+            list
+        ),
+    )
+}
+
+/// The EPUB 2 table of contents, still read by older e-readers
+fn ncx(title: &str, identifier: &str, sections: &[Section]) -> String {
+    let mut points = String::new();
+    let mut index = 0;
+    for (number, section) in sections.iter().enumerate() {
+        // a feed points at its first article and shares its place in the reading order
+        points.push_str(&format!(
+            "<navPoint id=\"feed{}\" playOrder=\"{}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/>\n",
+            number + 1,
+            index + 1,
+            escape_text(&section.feed),
+            chapter_file(index)
+        ));
+        for article in &section.articles {
+            points.push_str(&format!(
+                "  <navPoint id=\"article{}\" playOrder=\"{}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/></navPoint>\n",
+                index + 1,
+                index + 1,
+                escape_text(&article.title),
+                chapter_file(index)
+            ));
+            index += 1;
+        }
+        points.push_str("</navPoint>\n");
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+<head><meta name="dtb:uid" content="{}"/></head>
+<docTitle><text>{}</text></docTitle>
+<navMap>
+{}</navMap>
+</ncx>
+"#,
+        identifier,
+        escape_text(title),
+        points
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::{events::Event, Reader};
+    use regex::Regex;
+
+    /// Fails unless every element is closed in order and every entity is one XML knows
+    fn assert_well_formed(xml: &str) {
+        let mut reader = Reader::from_str(xml);
+        let mut open = vec![];
+        loop {
+            let event = reader.read_event().unwrap();
+            if let Event::Start(element) | Event::Empty(element) = &event {
+                for attribute in element.attributes() {
+                    attribute
+                        .unwrap()
+                        .decode_and_unescape_value(&reader)
+                        .unwrap();
+                }
+            }
+            match event {
+                Event::Start(element) => open.push(element.name().as_ref().to_vec()),
+                Event::End(element) => {
+                    assert_eq!(open.pop().as_deref(), Some(element.name().as_ref()))
+                }
+                Event::Text(text) => {
+                    text.unescape().unwrap();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        assert!(open.is_empty(), "{} is not closed", xml);
+    }
+
+    fn sections() -> Vec<Section> {
+        let article = |feed: &str, title: &str, language: Option<&str>| Article {
+            feed: feed.to_string(),
+            title: title.to_string(),
+            content: "<p>Text</p>".to_string(),
+            language: language.map(str::to_string),
+            ..Default::default()
+        };
+        super::sections(&[
+            article("First & best", "One", Some("fr")),
+            article("Second", "Two <3", Some("en")),
+            article("First & best", "Three", Some("fr")),
+        ])
+    }
+
+    #[test]
+    fn content_is_well_formed_xhtml() {
+        let html = "<p>One<br>two <b>bold <i>both</b> italic</i></p><ul><li>a<li>b</ul>\
+            <img src=\"https://a.example/never-shown.png\" alt=\"A & B\"><hr>\
+            <script>if (a < b) {}</script><custom>kept</custom> &nbsp;&amp; \
+            <a href=\"javascript:alert(1)\" onclick=\"x\">link</a><table><tr><td colspan=2>cell\
+            \x0c\x1b[0m<abbr title=\"\x07\">a</abbr>";
+        let xhtml = content(html, &mut Pictures::default());
+        assert_well_formed(&format!("<body>{}</body>", xhtml));
+        assert!(xhtml.contains("[A &amp; B]"));
+        assert!(xhtml.contains("kept"));
+        assert!(!xhtml.contains(['\x07', '\x0c', '\x1b']));
+        assert!(
+            !xhtml.contains("script")
+                && !xhtml.contains("javascript")
+                && !xhtml.contains("onclick")
+        );
+
+        let article = Article {
+            title: "Fish & <chips>\x0c".to_string(),
+            link: Some("https://a.example/?a=1&b=2".to_string()),
+            content: html.to_string(),
+            ..Default::default()
+        };
+        let chapter = chapter(&article, &mut Pictures::default());
+        assert_well_formed(&chapter);
+        assert!(!chapter.contains('\x0c'));
+    }
+
+    #[test]
+    fn the_book_is_in_the_language_of_most_articles() {
+        let sections = sections();
+        assert_eq!(language(&sections), "fr");
+        assert_eq!(language(&sections[1..]), "en");
+        assert_eq!(language(&[]), "und");
+        let chapter = chapter(&sections[0].articles[0], &mut Pictures::default());
+        assert!(chapter.contains(" xml:lang=\"fr\" lang=\"fr\""));
+    }
+
+    #[test]
+    fn package_and_navigation_list_the_same_chapters() {
+        let sections = sections();
+        let package = package(
+            "Book & co",
+            "urn:trss:1",
+            &language(&sections),
+            Timestamp::now(),
+            3,
+            &Pictures::default(),
+        );
+        let navigation = navigation("Book & co", "fr", &sections);
+        let ncx = ncx("Book & co", "urn:trss:1", &sections);
+        for xml in [&package, &navigation, &ncx] {
+            assert_well_formed(xml);
+        }
+        assert!(package.contains("<dc:language>fr</dc:language>"));
+
+        let all = |pattern: &str, text: &str| -> Vec<String> {
+            Regex::new(pattern)
+                .unwrap()
+                .captures_iter(text)
+                .map(|found| found[1].to_string())
+                .collect()
+        };
+        let chapters: Vec<String> = (0..3).map(chapter_file).collect();
+        // the spine reads the chapters in the order of the manifest
+        let items = all(r#"<item id="(article\d+)""#, &package);
+        assert_eq!(items, all(r#"<itemref idref="([^"]+)""#, &package));
+        assert_eq!(all(r#"href="(text/[^"]+)""#, &package), chapters);
+        // sections keep their articles together, in the order the feeds first appear
+        assert_eq!(all(r#"<a href="([^"]+)""#, &navigation), chapters);
+        assert_eq!(
+            all(
+                r#"id="article\d+" playOrder="\d+"><navLabel><text>[^<]*</text></navLabel><content src="([^"]+)""#,
+                &ncx
+            ),
+            chapters
+        );
+        assert_eq!(
+            all(r#"<li><a href="[^"]+">([^<]+)</a>"#, &navigation),
+            ["One", "Three", "Two &lt;3"]
+        );
+    }
+}
@@ -3,24 +3,52 @@ use confy::ConfyError;
 use std::error;
 use std::fmt;
 
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     ConfigurationError(ConfyError),
     WriteError(std::io::Error),
+    RuleError(String),
+    QueryError(String),
+    NetworkError(reqwest::Error),
+    ExportError(String),
+    UsageError(String),
+    ServeError(String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::ConfigurationError(confy_error) => write!(
-                f,
-                "An error occured in the configuration framework: {}",
-                confy_error
-            ),
+            Self::ConfigurationError(confy_error) => {
+                write!(
+                    f,
+                    "An error occured in the configuration framework: {}",
+                    confy_error
+                )?;
+                // the TOML error says which line is wrong
+                match error::Error::source(confy_error) {
+                    Some(source) => write!(f, ": {}", source),
+                    None => Ok(()),
+                }
+            }
             Error::WriteError(io_error) => write!(
                 f,
                 "An error occurred when writing to a file or the terminal: {}",
                 io_error
             ),
+            Error::RuleError(message) => write!(f, "A filter rule is invalid: {}", message),
+            Error::QueryError(message) => {
+                write!(f, "A virtual feed has an invalid query: {}", message)
+            }
+            Error::NetworkError(reqwest_error) => {
+                write!(f, "The HTTP client could not be set up: {}", reqwest_error)
+            }
+            Error::ExportError(message) => {
+                write!(f, "The articles could not be exported: {}", message)
+            }
+            Error::UsageError(message) => write!(f, "{}", message),
+            Error::ServeError(message) => {
+                write!(f, "The feeds could not be served: {}", message)
+            }
         }
     }
 }
@@ -28,16 +56,37 @@ impl fmt::Display for Error {
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::ConfigurationError(confy_error) => write!(
-                f,
-                "An error occured in the configuration framework: {}",
-                confy_error
-            ),
+            Self::ConfigurationError(confy_error) => {
+                write!(
+                    f,
+                    "An error occured in the configuration framework: {}",
+                    confy_error
+                )?;
+                // the TOML error says which line is wrong
+                match error::Error::source(confy_error) {
+                    Some(source) => write!(f, ": {}", source),
+                    None => Ok(()),
+                }
+            }
             Error::WriteError(io_error) => write!(
                 f,
                 "An error occurred when writing to a file or the terminal: {}",
                 io_error
             ),
+            Error::RuleError(message) => write!(f, "A filter rule is invalid: {}", message),
+            Error::QueryError(message) => {
+                write!(f, "A virtual feed has an invalid query: {}", message)
+            }
+            Error::NetworkError(reqwest_error) => {
+                write!(f, "The HTTP client could not be set up: {}", reqwest_error)
+            }
+            Error::ExportError(message) => {
+                write!(f, "The articles could not be exported: {}", message)
+            }
+            Error::UsageError(message) => write!(f, "{}", message),
+            Error::ServeError(message) => {
+                write!(f, "The feeds could not be served: {}", message)
+            }
         }
     }
 }
@@ -54,4 +103,16 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<regex::Error> for Error {
+    fn from(value: regex::Error) -> Self {
+        Self::RuleError(value.to_string())
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        Self::NetworkError(value)
+    }
+}
+
 impl error::Error for Error {}
@@ -0,0 +1,556 @@
+//! Writes lists of articles to files others can read: JSON, Markdown or an Atom, RSS or JSON feed.
+use crate::{config, error::Error, rss::Article};
+
+use atom_syndication::{
+    CategoryBuilder, ContentBuilder, EntryBuilder, FeedBuilder, FixedDateTime, LinkBuilder,
+    PersonBuilder, Text,
+};
+use jiff::{fmt::rfc2822, tz::TimeZone, Timestamp};
+use rss::{ChannelBuilder, EnclosureBuilder, GuidBuilder, ItemBuilder};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Longest file or folder name made from a template, in bytes as file systems count them, under
+/// their 255 so `.part` or ` (2)` still fit
+const MAX_NAME: usize = 200;
+
+/// File formats articles can be exported to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    Markdown,
+    Atom,
+}
+
+impl Format {
+    /// Format matching the extension of a file name
+    pub fn from_path(path: &Path) -> Result<Format, Error> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("json") => Ok(Format::Json),
+            Some("md" | "markdown") => Ok(Format::Markdown),
+            Some("atom" | "xml") => Ok(Format::Atom),
+            _ => Err(Error::ExportError(format!(
+                "{} should end with .json, .md or .atom",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Path typed by the user, `~` stands for the home directory
+pub(crate) fn expand(path: &str) -> PathBuf {
+    match (path.trim().strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path.trim()),
+    }
+}
+
+/// Path of a file saved for an article, from a template where `{feed}`, `{title}`, `{date}`,
+/// `{episode}` and `{ext}` are replaced. Every `/` of the template makes a folder.
+pub(crate) fn file_path(
+    directory: &str,
+    template: &str,
+    article: &Article,
+    extension: &str,
+) -> PathBuf {
+    let date = article
+        .published()
+        .map(|published| {
+            published
+                .to_zoned(TimeZone::system())
+                .strftime("%Y-%m-%d")
+                .to_string()
+        })
+        .unwrap_or_else(|| "undated".to_string());
+
+    let mut path = expand(directory);
+    for part in template.split('/').filter(|part| !part.is_empty()) {
+        let name = part
+            .replace("{feed}", &sanitize(&article.feed))
+            .replace("{title}", &sanitize(&article.title))
+            .replace("{date}", &date)
+            .replace(
+                "{episode}",
+                &sanitize(article.episode.as_deref().unwrap_or_default()),
+            )
+            .replace("{ext}", extension);
+        path.push(truncate(name.trim()));
+    }
+    path
+}
+
+/// Makes a value safe to use in a file name
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string()
+}
+
+fn truncate(name: &str) -> String {
+    if name.len() <= MAX_NAME {
+        return name.to_string();
+    }
+    // keep the extension
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if extension.len() <= 5 => {
+            let stem = prefix(stem, MAX_NAME - extension.len() - 1);
+            format!("{}.{}", stem.trim_end(), extension)
+        }
+        _ => prefix(name, MAX_NAME).to_string(),
+    }
+}
+
+/// The longest start of the text that fits in a number of bytes without splitting a character
+fn prefix(text: &str, bytes: usize) -> &str {
+    let end = text
+        .char_indices()
+        .map(|(start, c)| start + c.len_utf8())
+        .take_while(|end| *end <= bytes)
+        .last()
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// Writes the articles to a file in the format its extension asks for
+pub(crate) fn write(title: &str, articles: &[Article], path: &Path) -> Result<(), Error> {
+    let text = match Format::from_path(path)? {
+        Format::Json => json(articles)?,
+        Format::Markdown => markdown(title, articles),
+        Format::Atom => atom(title, articles),
+    };
+    fs::write(path, text)?;
+    Ok(())
+}
+
+pub(crate) fn json(articles: &[Article]) -> Result<String, Error> {
+    serde_json::to_string_pretty(articles).map_err(|e| Error::ExportError(e.to_string()))
+}
+
+/// A list of links with the feed, author, date and summary of every article
+pub(crate) fn markdown(title: &str, articles: &[Article]) -> String {
+    let mut text = format!("# {}\n", title);
+    for article in articles {
+        text.push_str(&match &article.link {
+            Some(link) => format!("\n## [{}](<{}>)\n\n", escape(&article.title), link),
+            None => format!("\n## {}\n\n", escape(&article.title)),
+        });
+
+        let mut about = vec![escape(&article.feed)];
+        if !article.author.is_empty() {
+            about.push(escape(&article.author));
+        }
+        if let Some(published) = article.published() {
+            about.push(published.strftime("%Y-%m-%d").to_string());
+        }
+        text.push_str(&format!("*{}*\n", about.join(" · ")));
+
+        if let Some(summary) = article.subtitle.as_deref().filter(|s| !s.trim().is_empty()) {
+            text.push_str(&format!("\n{}\n", summary.trim()));
+        }
+    }
+    text
+}
+
+/// An article in Markdown, with its title, feed, author, date, link, id and tags in a YAML front
+/// matter
+pub(crate) fn article_markdown(article: &Article) -> String {
+    let mut text = String::from("---\n");
+    text.push_str(&format!("title: {}\n", yaml(&article.title)));
+    text.push_str(&format!("feed: {}\n", yaml(&article.feed)));
+    if !article.author.is_empty() {
+        text.push_str(&format!("author: {}\n", yaml(&article.author)));
+    }
+    if let Some(published) = article.published() {
+        text.push_str(&format!("date: {}\n", published));
+    }
+    if let Some(link) = &article.link {
+        text.push_str(&format!("link: {}\n", yaml(link)));
+    }
+    text.push_str(&format!("id: {}\n", yaml(&article.id)));
+    if !article.source.is_empty() {
+        text.push_str(&format!("source: {}\n", yaml(&article.source)));
+    }
+    if !article.categories.is_empty() {
+        text.push_str("tags:\n");
+        for category in &article.categories {
+            text.push_str(&format!("  - {}\n", yaml(category)));
+        }
+    }
+    text.push_str("---\n\n");
+    text.push_str(html2md::parse_html(&article.content).trim());
+    text.push('\n');
+    text
+}
+
+/// Saves an article as Markdown at the path of the template, returns the path. Saving it again
+/// replaces its file, another article with the same path gets a numbered one like `title (2).md`.
+pub(crate) fn save(config: &config::Save, article: &Article) -> Result<PathBuf, Error> {
+    let path = file_path(&config.directory, &config.template, article, "md");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let path = (1..)
+        .map(|number| numbered(&path, number))
+        .find(|path| match fs::read_to_string(path) {
+            Ok(saved) => is_saved(&saved, article),
+            Err(_) => !path.exists(),
+        })
+        .unwrap_or(path);
+    fs::write(&path, article_markdown(article))?;
+    Ok(path)
+}
+
+/// The path with ` (number)` before its extension, the path itself for 1
+pub(crate) fn numbered(path: &Path, number: usize) -> PathBuf {
+    if number == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{} ({}).{}", stem, number, extension.to_string_lossy()),
+        None => format!("{} ({})", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether a saved file is the article, ids are only unique within a feed. Files saved before the
+/// feed was written down are told apart by their id alone.
+fn is_saved(saved: &str, article: &Article) -> bool {
+    let id = format!("id: {}", yaml(&article.id));
+    let source = format!("source: {}", yaml(&article.source));
+    front_matter(saved).any(|line| line == id)
+        && front_matter(saved).all(|line| !line.starts_with("source: ") || line == source)
+}
+
+/// Lines of the YAML front matter of a saved article
+fn front_matter(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().skip(1).take_while(|line| *line != "---")
+}
+
+/// A YAML double-quoted string
+fn yaml(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push(' '),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// An Atom feed of the articles with their full content
+pub(crate) fn atom(title: &str, articles: &[Article]) -> String {
+    let entries: Vec<_> = articles
+        .iter()
+        .map(|article| {
+            let updated = date(article.published().unwrap_or_else(Timestamp::now));
+            EntryBuilder::default()
+                .id(article.id.clone())
+                .title(Text::plain(article.title.clone()))
+                .updated(updated)
+                .published(article.published().map(date))
+                .authors(
+                    Some(article.author.clone())
+                        .filter(|author| !author.is_empty())
+                        .map(|author| PersonBuilder::default().name(author).build())
+                        .into_iter()
+                        .collect::<Vec<_>>(),
+                )
+                .links(
+                    article
+                        .link
+                        .iter()
+                        .map(|link| {
+                            LinkBuilder::default()
+                                .href(link.clone())
+                                .rel("alternate".to_string())
+                                .build()
+                        })
+                        .chain(article.enclosures.iter().map(|enclosure| {
+                            LinkBuilder::default()
+                                .href(enclosure.url.clone())
+                                .rel("enclosure".to_string())
+                                .mime_type(Some(enclosure.mime_type.clone()))
+                                .length(enclosure.length.map(|length| length.to_string()))
+                                .build()
+                        }))
+                        .collect::<Vec<_>>(),
+                )
+                .categories(
+                    article
+                        .categories
+                        .iter()
+                        .map(|category| CategoryBuilder::default().term(category.clone()).build())
+                        .collect::<Vec<_>>(),
+                )
+                .summary(article.subtitle.clone().map(Text::plain))
+                .content(
+                    ContentBuilder::default()
+                        .content_type(Some("html".to_string()))
+                        .value(Some(article.content.clone()))
+                        .build(),
+                )
+                .build()
+        })
+        .collect();
+
+    FeedBuilder::default()
+        .id(format!("urn:{}:{}", env!("CARGO_CRATE_NAME"), slug(title)))
+        .title(Text::plain(title.to_string()))
+        .updated(date(Timestamp::now()))
+        .entries(entries)
+        .build()
+        .to_string()
+}
+
+/// An RSS 2.0 feed of the articles with their full content, `link` is where it is served
+pub(crate) fn rss(title: &str, link: &str, articles: &[Article]) -> String {
+    let items: Vec<_> = articles
+        .iter()
+        .map(|article| {
+            ItemBuilder::default()
+                .title(Some(article.title.clone()))
+                .link(article.link.clone())
+                .description(Some(article.content.clone()))
+                .author(Some(article.author.clone()).filter(|author| !author.is_empty()))
+                .guid(Some(
+                    GuidBuilder::default()
+                        .value(article.id.clone())
+                        .permalink(false)
+                        .build(),
+                ))
+                .pub_date(article.published().and_then(rfc2822_date))
+                .categories(
+                    article
+                        .categories
+                        .iter()
+                        .map(|category| rss::CategoryBuilder::default().name(category).build())
+                        .collect::<Vec<_>>(),
+                )
+                // RSS items hold a single enclosure
+                .enclosure(article.enclosures.first().map(|enclosure| {
+                    EnclosureBuilder::default()
+                        .url(enclosure.url.clone())
+                        .length(enclosure.length.unwrap_or_default().to_string())
+                        .mime_type(enclosure.mime_type.clone())
+                        .build()
+                }))
+                .build()
+        })
+        .collect();
+
+    ChannelBuilder::default()
+        .title(title)
+        .link(link)
+        .description(title)
+        .generator(Some(env!("CARGO_CRATE_NAME").to_string()))
+        .last_build_date(rfc2822_date(Timestamp::now()))
+        .items(items)
+        .build()
+        .to_string()
+}
+
+fn rfc2822_date(timestamp: Timestamp) -> Option<String> {
+    rfc2822::DateTimePrinter::new()
+        .timestamp_to_string(&timestamp)
+        .ok()
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    feed_url: &'a str,
+    items: Vec<JsonItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonItem<'a> {
+    id: &'a str,
+    title: &'a str,
+    content_html: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonAuthor<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<JsonAttachment<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonAuthor<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonAttachment<'a> {
+    url: &'a str,
+    mime_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_in_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_in_seconds: Option<u64>,
+}
+
+/// A JSON Feed 1.1 of the articles with their full content, `url` is where it is served
+pub(crate) fn json_feed(title: &str, url: &str, articles: &[Article]) -> Result<String, Error> {
+    let items = articles
+        .iter()
+        .map(|article| JsonItem {
+            id: &article.id,
+            title: &article.title,
+            content_html: &article.content,
+            url: article.link.as_deref(),
+            summary: article.subtitle.as_deref(),
+            date_published: article.published().map(|published| published.to_string()),
+            authors: Some(article.author.as_str())
+                .filter(|author| !author.is_empty())
+                .map(|name| JsonAuthor { name })
+                .into_iter()
+                .collect(),
+            tags: &article.categories,
+            attachments: article
+                .enclosures
+                .iter()
+                .map(|enclosure| JsonAttachment {
+                    url: &enclosure.url,
+                    mime_type: &enclosure.mime_type,
+                    size_in_bytes: enclosure.length,
+                    duration_in_seconds: enclosure.duration,
+                })
+                .collect(),
+        })
+        .collect();
+
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title,
+        feed_url: url,
+        items,
+    };
+    serde_json::to_string_pretty(&feed).map_err(|e| Error::ExportError(e.to_string()))
+}
+
+fn date(timestamp: Timestamp) -> FixedDateTime {
+    FixedDateTime::parse_from_rfc3339(&timestamp.to_string()).unwrap_or_default()
+}
+
+/// Lowercase words joined by dashes, for identifiers
+pub(crate) fn slug(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Escapes the characters Markdown would read as formatting
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '[' | ']' | '`' | '#' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn long_names_are_cut_in_bytes() {
+        let title = "長".repeat(120);
+        let name = truncate(&format!("{}.mp3", title));
+        assert!(name.len() <= MAX_NAME);
+        assert!(name.ends_with("長.mp3"));
+        assert_eq!(truncate(&title).len(), MAX_NAME / 3 * 3);
+        assert_eq!(truncate("short.mp3"), "short.mp3");
+    }
+
+    #[test]
+    fn articles_with_the_same_name_are_numbered() {
+        let directory = env::temp_dir().join(format!("trss-save-{}", std::process::id()));
+        let config = config::Save {
+            directory: directory.to_string_lossy().into_owned(),
+            template: "{feed}/{title}.{ext}".to_string(),
+        };
+        let article = |id: &str| Article {
+            id: id.to_string(),
+            feed: "Blog".to_string(),
+            title: "Weekly update".to_string(),
+            ..Default::default()
+        };
+
+        let first = save(&config, &article("1")).unwrap();
+        let second = save(&config, &article("2")).unwrap();
+        let again = save(&config, &article("1")).unwrap();
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(first, directory.join("Blog").join("Weekly update.md"));
+        assert_eq!(second, directory.join("Blog").join("Weekly update (2).md"));
+        assert_eq!(again, first);
+    }
+
+    #[test]
+    fn articles_of_other_feeds_are_not_replaced() {
+        let directory = env::temp_dir().join(format!("trss-save-feeds-{}", std::process::id()));
+        let config = config::Save {
+            directory: directory.to_string_lossy().into_owned(),
+            template: "{title}.{ext}".to_string(),
+        };
+        let article = |source: &str| Article {
+            id: "1".to_string(),
+            source: source.to_string(),
+            title: "Hello world".to_string(),
+            ..Default::default()
+        };
+        // saved before the source was written down
+        fs::create_dir_all(&directory).unwrap();
+        let legacy = directory.join("Hello world.md");
+        fs::write(
+            &legacy,
+            "---\ntitle: \"Hello world\"\nid: \"1\"\n---\n\nOld\n",
+        )
+        .unwrap();
+
+        let first = save(&config, &article("https://a.example/feed")).unwrap();
+        let second = save(&config, &article("https://b.example/feed")).unwrap();
+        let again = save(&config, &article("https://a.example/feed")).unwrap();
+        let saved = fs::read_to_string(&second).unwrap();
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(first, legacy);
+        assert_eq!(second, directory.join("Hello world (2).md"));
+        assert_eq!(again, first);
+        assert!(saved.contains("source: \"https://b.example/feed\"\n"));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    config::{Action, Rule},
+    error::Error,
+    rss::{Article, Website},
+};
+
+use jiff::{SignedDuration, Timestamp};
+use regex::{Regex, RegexBuilder};
+
+/// A criterion of a rule, ready to be matched
+enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn new(pattern: &str, regex: bool) -> Result<Pattern, Error> {
+        Ok(if regex {
+            Pattern::Regex(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+        } else {
+            Pattern::Substring(pattern.to_lowercase())
+        })
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Pattern::Substring(pattern) => text.to_lowercase().contains(pattern),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+struct CompiledRule {
+    feed: Option<Pattern>,
+    title: Option<Pattern>,
+    author: Option<Pattern>,
+    category: Option<Pattern>,
+    content: Option<Pattern>,
+    older_than: Option<SignedDuration>,
+    action: Action,
+    color: Option<String>,
+}
+
+impl CompiledRule {
+    fn new(rule: &Rule) -> Result<CompiledRule, Error> {
+        // a rule without criteria would match every article of every feed
+        let patterns = [
+            &rule.feed,
+            &rule.title,
+            &rule.author,
+            &rule.category,
+            &rule.content,
+        ];
+        if patterns.iter().all(|pattern| pattern.is_none()) && rule.older_than_days.is_none() {
+            return Err(Error::RuleError(
+                "every rule needs a feed, title, author, category, content or older_than_days"
+                    .to_string(),
+            ));
+        }
+
+        let pattern = |criterion: &Option<String>| {
+            criterion
+                .as_deref()
+                .map(|p| Pattern::new(p, rule.regex))
+                .transpose()
+        };
+
+        Ok(CompiledRule {
+            feed: pattern(&rule.feed)?,
+            title: pattern(&rule.title)?,
+            author: pattern(&rule.author)?,
+            category: pattern(&rule.category)?,
+            content: pattern(&rule.content)?,
+            older_than: rule
+                .older_than_days
+                .map(|days| SignedDuration::from_hours(i64::from(days) * 24)),
+            action: rule.action,
+            color: rule.color.clone(),
+        })
+    }
+
+    /// Whether the rule applies to the feed at all, checked once per website
+    fn matches_feed(&self, url: &str, website: &Website) -> bool {
+        self.feed
+            .as_ref()
+            .is_none_or(|feed| feed.is_match(url) || feed.is_match(&website.name))
+    }
+
+    fn matches(&self, article: &Article, now: Timestamp) -> bool {
+        let criterion = |pattern: &Option<Pattern>, text: &str| {
+            pattern.as_ref().is_none_or(|p| p.is_match(text))
+        };
+
+        criterion(&self.title, &article.title)
+            && criterion(&self.author, &article.author)
+            && self
+                .category
+                .as_ref()
+                .is_none_or(|category| article.categories.iter().any(|c| category.is_match(c)))
+            && self.content.as_ref().is_none_or(|content| {
+                content.is_match(&article.content)
+                    || article
+                        .subtitle
+                        .as_deref()
+                        .is_some_and(|subtitle| content.is_match(subtitle))
+            })
+            && self.older_than.is_none_or(|age| {
+                article
+                    .published()
+                    .is_some_and(|published| now.duration_since(published) > age)
+            })
+    }
+}
+
+/// The kill-file rules of the configuration, applied to every website when it is fetched
+pub(crate) struct Filter {
+    rules: Vec<CompiledRule>,
+}
+
+impl Filter {
+    pub fn new(rules: &[Rule]) -> Result<Filter, Error> {
+        Ok(Filter {
+            rules: rules
+                .iter()
+                .map(CompiledRule::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Drops the hidden articles of a website and flags the ones to mark read or highlight
+    pub fn apply(&self, url: &str, website: &mut Website) {
+        let rules: Vec<&CompiledRule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches_feed(url, website))
+            .collect();
+        if rules.is_empty() {
+            return;
+        }
+
+        let now = Timestamp::now();
+        website.articles.retain_mut(|article| {
+            let matching: Vec<&CompiledRule> = rules
+                .iter()
+                .copied()
+                .filter(|rule| rule.matches(article, now))
+                .collect();
+
+            for rule in matching {
+                match rule.action {
+                    Action::Hide => return false,
+                    Action::Read => article.read = true,
+                    Action::Highlight => {
+                        article.color = rule.color.clone().or(Some("yellow".to_string()))
+                    }
+                }
+            }
+            true
+        });
+    }
+}
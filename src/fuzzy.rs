@@ -0,0 +1,108 @@
+/// Bonus for a character right after the previous match
+const CONSECUTIVE: i64 = 8;
+/// Bonus for a character starting a word
+const WORD_START: i64 = 6;
+/// Largest penalty for the characters skipped between two matches
+const MAX_GAP: i64 = 5;
+
+/// A candidate matching the pattern typed in the finder
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Match {
+    pub score: i64,
+    /// Indices of the matched characters in the candidate
+    pub positions: Vec<usize>,
+}
+
+/// Matches the characters of `pattern` in order anywhere in `text` ignoring case, the way fzf
+/// does. Runs of consecutive characters and matches at the start of words score higher.
+pub(crate) fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let text: Vec<char> = text.chars().collect();
+    let mut positions = vec![];
+    let mut score = 0;
+    let mut start = 0;
+
+    for p in pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+    {
+        let offset = text[start..]
+            .iter()
+            .position(|c| c.to_lowercase().next() == Some(p))?;
+        let i = start + offset;
+
+        score += 1;
+        if positions.last().is_some_and(|last| last + 1 == i) {
+            score += CONSECUTIVE;
+        }
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += WORD_START;
+        }
+        if !positions.is_empty() {
+            score -= (offset as i64).min(MAX_GAP);
+        }
+
+        positions.push(i);
+        start = i + 1;
+    }
+
+    Some(Match { score, positions })
+}
+
+/// Indices of the candidates matching the pattern, best first
+pub(crate) fn filter<'a>(pattern: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<usize> {
+    let mut matches: Vec<(usize, i64)> = candidates
+        .enumerate()
+        .filter_map(|(i, candidate)| fuzzy_match(pattern, candidate).map(|m| (i, m.score)))
+        .collect();
+
+    // the sort is stable so equal scores keep the order of the list
+    matches.sort_by_key(|(_, score)| -score);
+    matches.into_iter().map(|(i, _)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(pattern: &str, text: &str) -> i64 {
+        fuzzy_match(pattern, text).unwrap().score
+    }
+
+    #[test]
+    fn characters_match_in_order_ignoring_case() {
+        assert_eq!(
+            fuzzy_match("RuSt", "trust me"),
+            Some(Match {
+                score: 4 + 3 * CONSECUTIVE,
+                positions: vec![1, 2, 3, 4],
+            })
+        );
+        assert_eq!(fuzzy_match("tsur", "trust me"), None);
+        assert_eq!(fuzzy_match("x", "trust me"), None);
+        // spaces in the pattern are ignored
+        assert_eq!(
+            fuzzy_match("t m", "trust me").unwrap().positions,
+            vec![0, 6]
+        );
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+    }
+
+    #[test]
+    fn runs_and_word_starts_score_higher() {
+        assert!(score("news", "news feed") > score("news", "nowhere else was"));
+        assert!(score("f", "rust feeds") > score("f", "surfing"));
+        // a run at the start of a word beats the same run inside a word
+        assert!(score("feed", "the feed") > score("feed", "unfeedable"));
+        // longer gaps cost more, up to a limit
+        assert!(score("ab", "a-b") > score("ab", "a----b"));
+        assert_eq!(score("ab", "a------b"), score("ab", "a----------b"));
+    }
+
+    #[test]
+    fn filter_sorts_by_score_keeping_ties_in_order() {
+        let candidates = ["unfeedable", "the feed", "nothing", "feed"];
+        assert_eq!(filter("feed", candidates.into_iter()), [1, 3, 0]);
+        assert_eq!(filter("", candidates.into_iter()), [0, 1, 2, 3]);
+    }
+}
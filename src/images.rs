@@ -0,0 +1,542 @@
+//! Images of articles in the reader.
+//!
+//! `<img>` elements are taken out of the HTML before it is turned into text and replaced by a
+//! numbered placeholder like `[image 1: a cat]`. The placeholders are found again by a marker made
+//! up for each article, so its text can never pass for one. When images are on, the lines under
+//! the placeholder are left empty and the image is drawn there, with the kitty graphics protocol,
+//! sixel or Unicode half blocks. Images are downloaded the first time an article showing them is
+//! read, and kept in `~/.cache/trss/images`.
+use crate::{config, error::Error};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use html_escape::decode_html_entities;
+use icy_sixel::SixelImage;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, RgbImage};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Position, Rect},
+    style::{Color, Style},
+    widgets::Widget,
+};
+use regex::{Captures, Regex};
+use reqwest::blocking::Client;
+use sha2::{Digest, Sha256};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Cursor, Read, Write},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        LazyLock,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Images larger than this are not downloaded
+const MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+/// Pixels of a terminal cell when the terminal doesn't say
+const CELL: (u16, u16) = (8, 16);
+
+/// Bytes of image data in one kitty escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+static IMG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<img\b[^>]*>").unwrap());
+static SRC: LazyLock<Regex> = LazyLock::new(|| attribute_regex("src"));
+static ALT: LazyLock<Regex> = LazyLock::new(|| attribute_regex("alt"));
+
+/// An `<img>` of an article
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Image {
+    pub src: String,
+    pub alt: String,
+}
+
+/// The HTML of an article with its images taken out, see `extract`
+pub(crate) struct Extracted {
+    pub html: String,
+    /// In the order of their number
+    pub images: Vec<Image>,
+    /// Marks the places of the images, the article does not contain it
+    token: String,
+}
+
+impl Extracted {
+    /// Replaces the markers left in the converted text by the alt text of the images, returns
+    /// the text and the line of every placeholder with its image
+    pub fn caption(&self, text: &str) -> (String, Vec<(usize, Image)>) {
+        let mut placeholders = vec![];
+        let lines: Vec<String> = text
+            .split('\n')
+            .enumerate()
+            .map(|(line, text)| {
+                let mut text = text.to_string();
+                while let Some(start) = text.find(&self.token) {
+                    let rest = &text[start + self.token.len()..];
+                    let Some(number) = rest
+                        .strip_prefix('i')
+                        .and_then(|rest| rest.split_once('i'))
+                        .and_then(|(number, _)| number.parse::<usize>().ok())
+                    else {
+                        break;
+                    };
+                    let marker = marker(&self.token, number);
+                    let Some(image) = number.checked_sub(1).and_then(|i| self.images.get(i)) else {
+                        text = text.replacen(&marker, "", 1);
+                        continue;
+                    };
+                    let alt: String = image.alt.chars().filter(|c| !c.is_control()).collect();
+                    let caption = match alt.trim() {
+                        "" => format!("[image {}]", number),
+                        alt => format!("[image {}: {}]", number, alt),
+                    };
+                    text = text.replacen(&marker, &caption, 1);
+                    placeholders.push((line, image.clone()));
+                }
+                text
+            })
+            .collect();
+        (lines.join("\n"), placeholders)
+    }
+}
+
+/// Replaces the images of an HTML fragment by markers, to be turned into numbered placeholders
+/// by `Extracted::caption` once the HTML is converted
+pub(crate) fn extract(html: &str) -> Extracted {
+    let token = loop {
+        let token = format!("trssimage{:016x}", fastrand::u64(..));
+        if !html.contains(&token) {
+            break token;
+        }
+    };
+    let mut images = vec![];
+    let html = IMG.replace_all(html, |captures: &Captures| {
+        let tag = &captures[0];
+        let Some(src) = attribute(tag, &SRC).filter(|src| !src.is_empty()) else {
+            return String::new();
+        };
+        images.push(Image {
+            src,
+            alt: attribute(tag, &ALT).unwrap_or_default(),
+        });
+        format!("<p>{}</p>", marker(&token, images.len()))
+    });
+
+    Extracted {
+        html: html.into_owned(),
+        images,
+        token,
+    }
+}
+
+fn marker(token: &str, number: usize) -> String {
+    // letters and digits only, Markdown leaves them alone
+    format!("{}i{}i", token, number)
+}
+
+/// An attribute of a tag, quoted or not
+fn attribute_regex(name: &str) -> Regex {
+    Regex::new(&format!(
+        r#"(?is)\s{}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#,
+        name
+    ))
+    .unwrap()
+}
+
+fn attribute(tag: &str, attribute: &Regex) -> Option<String> {
+    let captures = attribute.captures(tag)?;
+    let value = captures
+        .get(1)
+        .or(captures.get(2))
+        .or(captures.get(3))?
+        .as_str();
+    Some(decode_html_entities(value).trim().to_string())
+}
+
+/// Protocol of the configuration, or the one the terminal is known to support
+fn detect(protocol: config::Protocol) -> config::Protocol {
+    if protocol != config::Protocol::Auto {
+        return protocol;
+    }
+
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    if env::var_os("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term.contains("ghostty")
+        || matches!(program.as_str(), "WezTerm" | "ghostty")
+    {
+        config::Protocol::Kitty
+    } else if ["foot", "mlterm", "contour", "sixel"]
+        .iter()
+        .any(|name| term.contains(name))
+        || program == "iTerm.app"
+    {
+        config::Protocol::Sixel
+    } else {
+        config::Protocol::Halfblocks
+    }
+}
+
+/// Pixels of a terminal cell
+fn cell_size() -> (u16, u16) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            (size.width / size.columns, size.height / size.rows)
+        }
+        _ => CELL,
+    }
+}
+
+/// Where an image is drawn with the kitty or sixel protocol once the frame is on screen
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Placement {
+    src: String,
+    area: Rect,
+}
+
+/// Downloads, caches and draws the images of the reader
+pub(crate) struct Images {
+    config: config::Images,
+    protocol: config::Protocol,
+    cell: (u16, u16),
+    requests: Sender<String>,
+    arrivals: Receiver<(String, Result<DynamicImage, String>)>,
+    loaded: HashMap<String, Result<DynamicImage, String>>,
+    /// Images asked for, drawing only needs a shared reference
+    requested: RefCell<HashSet<String>>,
+    /// Images of the frame being drawn and of the frame on screen
+    placements: RefCell<Vec<Placement>>,
+    /// Half-block images resized to the size they were last drawn at, resizing on every frame is
+    /// slow
+    fitted: RefCell<HashMap<String, (u16, u16, RgbImage)>>,
+    shown: Vec<Placement>,
+}
+
+impl Images {
+    /// Starts the thread downloading the images
+    pub fn new(config: config::Images) -> Result<Images, Error> {
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        let cache = cache_dir();
+
+        let (requests, receiver) = mpsc::channel::<String>();
+        let (sender, arrivals) = mpsc::channel();
+        thread::spawn(move || {
+            for src in receiver {
+                let image = load(&client, &cache, &src).map_err(|e| e.to_string());
+                if sender.send((src, image)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Images {
+            protocol: detect(config.protocol),
+            config,
+            cell: cell_size(),
+            requests,
+            arrivals,
+            loaded: HashMap::new(),
+            requested: RefCell::new(HashSet::new()),
+            placements: RefCell::new(vec![]),
+            fitted: RefCell::new(HashMap::new()),
+            shown: vec![],
+        })
+    }
+
+    /// Lines left empty under a placeholder for its image, 0 when images are off
+    pub fn rows(&self) -> u16 {
+        if self.config.enabled {
+            self.config.height
+        } else {
+            0
+        }
+    }
+
+    /// Keeps the images that were downloaded since the last call
+    pub fn update(&mut self) {
+        while let Ok((src, image)) = self.arrivals.try_recv() {
+            self.loaded.insert(src, image);
+        }
+    }
+
+    /// Forgets the placements of the last frame, called before drawing a new one
+    pub fn clear(&self) {
+        self.placements.borrow_mut().clear();
+    }
+
+    /// Draws an image, or asks for it when it hasn't been downloaded yet
+    pub fn render(&self, src: &str, area: Rect, buffer: &mut Buffer) {
+        match self.loaded.get(src) {
+            Some(Ok(image)) => match self.protocol {
+                config::Protocol::Kitty | config::Protocol::Sixel => {
+                    self.placements.borrow_mut().push(Placement {
+                        src: src.to_string(),
+                        area,
+                    })
+                }
+                config::Protocol::Auto | config::Protocol::Halfblocks => {
+                    let mut fitted = self.fitted.borrow_mut();
+                    let stale = fitted.get(src).is_none_or(|(width, height, _)| {
+                        (*width, *height) != (area.width, area.height)
+                    });
+                    if stale {
+                        let image = image
+                            .resize(
+                                u32::from(area.width),
+                                u32::from(area.height) * 2,
+                                FilterType::Triangle,
+                            )
+                            .to_rgb8();
+                        fitted.insert(src.to_string(), (area.width, area.height, image));
+                    }
+                    if let Some((_, _, image)) = fitted.get(src) {
+                        HalfBlocks(image).render(area, buffer)
+                    }
+                }
+            },
+            Some(Err(error)) => {
+                buffer.set_stringn(
+                    area.x,
+                    area.y,
+                    format!("(could not load the image: {})", error),
+                    area.width as usize,
+                    Style::default(),
+                );
+            }
+            None => {
+                if self.requested.borrow_mut().insert(src.to_string()) {
+                    let _ = self.requests.send(src.to_string());
+                }
+                buffer.set_stringn(
+                    area.x,
+                    area.y,
+                    "(loading the image...)",
+                    area.width as usize,
+                    Style::default(),
+                );
+            }
+        }
+    }
+
+    /// Whether the images drawn with escape sequences moved since the last frame, sixel images
+    /// are only gone once the screen is cleared
+    pub fn moved(&self) -> bool {
+        *self.placements.borrow() != self.shown
+    }
+
+    pub fn needs_clear(&self) -> bool {
+        self.protocol == config::Protocol::Sixel && !self.shown.is_empty()
+    }
+
+    /// Writes the kitty or sixel escape sequences of the images of the frame on screen
+    pub fn show(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let placements = self.placements.borrow().clone();
+        if self.protocol == config::Protocol::Kitty {
+            // deletes every image placed before
+            write!(out, "\x1b_Ga=d,q=2\x1b\\")?;
+        }
+
+        for placement in &placements {
+            let Some(Ok(image)) = self.loaded.get(&placement.src) else {
+                continue;
+            };
+            let area = placement.area;
+            let fitted = image.resize(
+                u32::from(area.width) * u32::from(self.cell.0),
+                u32::from(area.height) * u32::from(self.cell.1),
+                FilterType::Triangle,
+            );
+
+            crossterm::queue!(out, crossterm::cursor::MoveTo(area.x, area.y))?;
+            match self.protocol {
+                config::Protocol::Kitty => kitty(out, &fitted, area)?,
+                _ => sixel(out, &fitted)?,
+            }
+        }
+        out.flush()?;
+
+        self.shown = placements;
+        Ok(())
+    }
+}
+
+/// Downloads an image into the cache unless it is there already, then decodes it
+fn load(
+    client: &Client,
+    cache: &PathBuf,
+    src: &str,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let path = cache.join(cache_name(src));
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            let response = client.get(src).send()?.error_for_status()?;
+            if response
+                .content_length()
+                .is_some_and(|length| length > MAX_SIZE)
+            {
+                return Err("the image is too large".into());
+            }
+            // chunked responses don't say their length
+            let mut bytes = vec![];
+            response.take(MAX_SIZE + 1).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 > MAX_SIZE {
+                return Err("the image is too large".into());
+            }
+            fs::create_dir_all(cache)?;
+            fs::write(&path, &bytes)?;
+            bytes
+        }
+    };
+
+    Ok(image::load_from_memory(&bytes)?)
+}
+
+fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(env!("CARGO_CRATE_NAME"))
+        .join("images")
+}
+
+/// Name of the file an image is cached in, a hash of its URL
+pub(crate) fn cache_name(src: &str) -> String {
+    format!("{:x}", Sha256::digest(src.as_bytes()))
+}
+
+/// Bytes of an image already downloaded for the reader
+pub(crate) fn cached(src: &str) -> Option<Vec<u8>> {
+    fs::read(cache_dir().join(cache_name(src))).ok()
+}
+
+/// Places an image with the kitty graphics protocol, the PNG is sent in chunks
+fn kitty(out: &mut impl Write, image: &DynamicImage, area: Rect) -> io::Result<()> {
+    let mut png = Cursor::new(vec![]);
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    let encoded = STANDARD.encode(png.into_inner());
+
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        if index == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=100,q=2,C=1,c={},r={},m={};",
+                area.width, area.height, more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+fn sixel(out: &mut impl Write, image: &DynamicImage) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let sixel =
+        SixelImage::try_from_rgba(image.to_rgba8().into_raw(), width as usize, height as usize)
+            .and_then(|sixel| sixel.encode())
+            .map_err(io::Error::other)?;
+    out.write_all(sixel.as_bytes())
+}
+
+/// An image drawn with `▀`, the foreground is the upper pixel and the background the lower one.
+/// It is already resized to the area, two pixels high per row.
+struct HalfBlocks<'a>(&'a RgbImage);
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buffer: &mut Buffer) {
+        let image = self.0;
+
+        for (x, y) in
+            (0..image.width()).flat_map(|x| (0..image.height()).step_by(2).map(move |y| (x, y)))
+        {
+            let position = Position::new(area.x + x as u16, area.y + (y / 2) as u16);
+            let Some(cell) = buffer.cell_mut(position) else {
+                continue;
+            };
+            let [r, g, b] = image.get_pixel(x, y).0;
+            cell.set_char('▀').set_fg(Color::Rgb(r, g, b));
+            if y + 1 < image.height() {
+                let [r, g, b] = image.get_pixel(x, y + 1).0;
+                cell.set_bg(Color::Rgb(r, g, b));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_are_read_quoted_or_not() {
+        let tag = "<IMG data-src=\"no\" SRC='a.png?x=1&amp;y=2' alt=\"A &quot;cat&quot;\" title=x>";
+        assert_eq!(attribute(tag, &SRC).as_deref(), Some("a.png?x=1&y=2"));
+        assert_eq!(attribute(tag, &ALT).as_deref(), Some("A \"cat\""));
+        assert_eq!(attribute("<img src=b.png>", &SRC).as_deref(), Some("b.png"));
+        assert_eq!(attribute("<img src=b.png>", &ALT), None);
+    }
+
+    #[test]
+    fn images_become_numbered_placeholders() {
+        let extracted = extract(
+            "<p>Before</p><img src=\"a.png\" alt=\"A\ncat\"><img alt=\"no source\">\
+             <p>[image 1] is not one</p><img src=\"b.png\">",
+        );
+        assert_eq!(
+            extracted.images,
+            [
+                Image {
+                    src: "a.png".to_string(),
+                    alt: "A\ncat".to_string()
+                },
+                Image {
+                    src: "b.png".to_string(),
+                    alt: String::new()
+                }
+            ]
+        );
+        assert!(!extracted.html.contains("<img"));
+
+        let converted = html2md::parse_html(&extracted.html);
+        let (text, placeholders) = extracted.caption(&converted);
+        let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(
+            lines,
+            [
+                "Before",
+                "[image 1: Acat]",
+                "[image 1] is not one",
+                "[image 2]"
+            ]
+        );
+        let placed: Vec<(&str, &str)> = placeholders
+            .iter()
+            .map(|(line, image)| (text.lines().nth(*line).unwrap(), image.src.as_str()))
+            .collect();
+        assert_eq!(
+            placed,
+            [("[image 1: Acat]", "a.png"), ("[image 2]", "b.png")]
+        );
+    }
+
+    #[test]
+    fn markers_are_not_in_the_article() {
+        let extracted = extract("<img src=\"a.png\">");
+        let token = extracted.token.clone();
+        let again = extract(&format!("{}i1i <img src=\"a.png\">", token));
+        assert_ne!(again.token, token);
+        let (text, placeholders) = again.caption(&again.html);
+        assert!(text.starts_with(&format!("{}i1i", token)));
+        assert_eq!(placeholders.len(), 1);
+    }
+}
@@ -5,10 +5,16 @@
 ///
 /// This is a very simple example:
 ///   * A input box always focused. Every character you type is registered
-///   here
+///     here
 ///   * Pressing Backspace erases a character
-///   * Pressing Enter pushes the current input in the history of previous
-///   messages
+///   * Pressing Enter looks for the feeds at the address typed and pushes
+///     the feed in the history of previous messages, or lets you choose
+///     one when the website has several
+use crate::{
+    discover::{discover, Candidate},
+    rss::Fetcher,
+};
+
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
@@ -19,7 +25,7 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Position},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Frame, Terminal,
 };
 use std::{error::Error, io};
@@ -28,6 +34,8 @@ use unicode_width::UnicodeWidthStr;
 enum InputMode {
     Normal,
     Editing,
+    /// Picking one of the feeds found for a website
+    Choosing,
 }
 
 /// App holds the state of the application
@@ -38,6 +46,11 @@ struct App {
     input_mode: InputMode,
     /// History of recorded messages
     messages: Vec<String>,
+    /// Feeds found for the last website typed
+    choices: Vec<Candidate>,
+    choice: ListState,
+    /// Outcome of the last search for feeds
+    status: String,
 }
 
 impl Default for App {
@@ -46,11 +59,37 @@ impl Default for App {
             input: String::new(),
             input_mode: InputMode::Normal,
             messages: Vec::new(),
+            choices: Vec::new(),
+            choice: ListState::default(),
+            status: String::new(),
         }
     }
 }
 
-pub(crate) fn main() -> Result<Vec<String>, Box<dyn Error>> {
+impl App {
+    /// Looks for the feeds of the address typed, records it right away when there is only one
+    fn discover(&mut self, fetcher: &Fetcher) {
+        let url: String = self.input.drain(..).collect();
+        match discover(url.trim(), fetcher) {
+            Ok(candidates) => match candidates.as_slice() {
+                [] => self.status = format!("No feed found at {}", url),
+                [candidate] => {
+                    self.status = String::new();
+                    self.messages.push(candidate.url.clone());
+                }
+                _ => {
+                    self.status = format!("{} feeds found at {}", candidates.len(), url);
+                    self.choices = candidates;
+                    self.choice.select(Some(0));
+                    self.input_mode = InputMode::Choosing;
+                }
+            },
+            Err(error) => self.status = format!("{}: {}", url, error),
+        }
+    }
+}
+
+pub(crate) fn main(fetcher: &Fetcher) -> Result<Vec<String>, Box<dyn Error>> {
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -60,7 +99,7 @@ pub(crate) fn main() -> Result<Vec<String>, Box<dyn Error>> {
 
     // create app and run it
     let app = App::default();
-    let res = run_app(&mut terminal, app)?;
+    let res = run_app(&mut terminal, app, fetcher)?;
 
     // restore terminal
     disable_raw_mode()?;
@@ -74,7 +113,11 @@ pub(crate) fn main() -> Result<Vec<String>, Box<dyn Error>> {
     Ok(res)
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<Vec<String>> {
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+    fetcher: &Fetcher,
+) -> io::Result<Vec<String>> {
     loop {
         terminal.draw(|f| ui(f, &app))?;
 
@@ -91,7 +134,10 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<V
                 },
                 InputMode::Editing => match key.code {
                     KeyCode::Enter => {
-                        app.messages.push(app.input.drain(..).collect());
+                        // fetching blocks, say what is going on first
+                        app.status = format!("Looking for feeds at {}", app.input);
+                        terminal.draw(|f| ui(f, &app))?;
+                        app.discover(fetcher);
                     }
                     KeyCode::Char(c) => {
                         app.input.push(c);
@@ -104,23 +150,45 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<V
                     }
                     _ => {}
                 },
+                InputMode::Choosing => match key.code {
+                    KeyCode::Down => {
+                        let last = app.choices.len() - 1;
+                        app.choice
+                            .select(app.choice.selected().map(|i| (i + 1).min(last)));
+                    }
+                    KeyCode::Up => {
+                        app.choice
+                            .select(app.choice.selected().map(|i| i.saturating_sub(1)));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(candidate) = app.choice.selected().map(|i| &app.choices[i]) {
+                            app.messages.push(candidate.url.clone());
+                        }
+                        app.status = String::new();
+                        app.input_mode = InputMode::Editing;
+                    }
+                    KeyCode::Esc => {
+                        app.status = String::new();
+                        app.input_mode = InputMode::Editing;
+                    }
+                    _ => {}
+                },
             }
         }
     }
 }
 
 fn ui(f: &mut Frame, app: &App) {
+    let mut choice = app.choice.clone();
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
-        .constraints(
-            [
-                Constraint::Length(1),
-                Constraint::Length(3),
-                Constraint::Min(1),
-            ]
-            .as_ref(),
-        )
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Min(1),
+        ])
         .split(f.area());
 
     let (msg, style) = match app.input_mode {
@@ -140,7 +208,18 @@ fn ui(f: &mut Frame, app: &App) {
                 Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to stop editing, "),
                 Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to record the message"),
+                Span::raw(" to add the website, "),
+                Span::raw(app.status.clone()),
+            ],
+            Style::default(),
+        ),
+        InputMode::Choosing => (
+            vec![
+                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" subscribes to the selected feed, "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" cancels. "),
+                Span::raw(app.status.clone()),
             ],
             Style::default(),
         ),
@@ -152,13 +231,13 @@ fn ui(f: &mut Frame, app: &App) {
 
     let input = Paragraph::new(app.input.clone())
         .style(match app.input_mode {
-            InputMode::Normal => Style::default(),
+            InputMode::Normal | InputMode::Choosing => Style::default(),
             InputMode::Editing => Style::default().fg(Color::Green),
         })
         .block(Block::default().borders(Borders::ALL).title("Input"));
     f.render_widget(input, chunks[1]);
     match app.input_mode {
-        InputMode::Normal =>
+        InputMode::Normal | InputMode::Choosing =>
             // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
             {}
 
@@ -182,5 +261,24 @@ fn ui(f: &mut Frame, app: &App) {
         .collect();
     let messages =
         List::new(messages).block(Block::default().borders(Borders::ALL).title("Messages"));
-    f.render_widget(messages, chunks[2]);
+
+    if let InputMode::Choosing = app.input_mode {
+        let choices: Vec<ListItem> = app
+            .choices
+            .iter()
+            .map(|candidate| {
+                ListItem::new(Line::from(Span::raw(format!(
+                    "{} - {}",
+                    candidate.title, candidate.url
+                ))))
+            })
+            .collect();
+        let choices = List::new(choices)
+            .block(Block::default().borders(Borders::ALL).title("Feeds"))
+            .highlight_style(Style::default().bg(Color::LightGreen))
+            .highlight_symbol(">> ");
+        f.render_stateful_widget(choices, chunks[2], &mut choice);
+    } else {
+        f.render_widget(messages, chunks[2]);
+    }
 }
@@ -2,22 +2,42 @@ use ansi_to_tui::IntoText;
 use crossterm::event::{self, Event, KeyCode};
 use ratatui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Position, Rect},
     style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, Borders, HighlightSpacing, List, ListItem, ListState, Paragraph, Wrap},
+    text::{Line, Span, Text},
+    widgets::{
+        Block, Borders, Clear, HighlightSpacing, LineGauge, List, ListItem, ListState, Paragraph,
+        Wrap,
+    },
     Frame, Terminal,
 };
 use std::{
-    collections::HashSet,
+    cell::RefCell,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeSet, HashSet},
     io,
+    sync::Arc,
     time::{Duration, Instant},
 };
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    config::Config,
+    config::{self, Config, Orientation, Sort},
+    download::{Downloader, Status},
+    enclosure, epub,
+    error::Error,
+    export,
+    filter::Filter,
+    fuzzy::{self, fuzzy_match},
+    images::{self, Image, Images},
     input,
-    rss::{example_feed, Article, Website},
+    query::Query,
+    refresh::{self, Refresher, Update},
+    rss::{Article, Fetcher, Website},
+    search::{self, Index},
+    starred::{self, Starred},
+    state::{self, State},
+    timeline::Timeline,
 };
 
 struct StatefulList<T> {
@@ -42,9 +62,13 @@ impl<T> StatefulList<T> {
 
     // Fix, this can be better
     fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+
         let i = match self.state.selected() {
             Some(i) => {
-                if i == self.items.len() - 1 {
+                if i >= self.items.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -57,6 +81,10 @@ impl<T> StatefulList<T> {
 
     // same here
     fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -73,8 +101,227 @@ impl<T> StatefulList<T> {
     fn unselect(&mut self) {
         self.state.select(None);
     }
+
+    fn selected(&self) -> Option<&T> {
+        self.state.selected().and_then(|i| self.items.get(i))
+    }
+}
+
+/// An entry of the sidebar, either a virtual feed, a folder or one of the subscriptions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Entry {
+    Timeline(Timeline),
+    /// Articles starred by the user
+    Starred,
+    /// Results of the last search
+    Search,
+    /// Position in the virtual feeds of the configuration
+    Query(usize),
+    /// Position in the folders, its websites are listed below it unless it is collapsed
+    Folder(usize),
+    /// Position in the websites and in the folders when it is listed inside one
+    Website {
+        index: usize,
+        folder: Option<usize>,
+    },
+}
+
+/// Which panel currently receives key presses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Websites,
+    Articles,
+    Reader,
+}
+
+/// What the text typed in the prompt is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PromptKind {
+    /// Search every article, the results show up as a virtual feed
+    Search,
+    /// Find text in the open article
+    Find,
+    /// Path the starred articles are exported to
+    Export,
+    /// Path the articles of the list are packed into an EPUB at
+    Epub,
+}
+
+/// A line of text input at the bottom of the screen
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+/// Which list the finder filters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FinderScope {
+    Sidebar,
+    Articles,
+    /// Titles of the articles of every website
+    Everywhere,
+}
+
+/// Where the finder jumps when an entry is chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    /// Position in the sidebar
+    Entry(usize),
+    /// Position in the article list
+    Article(usize),
+    /// Article of a website, as (website, article) indices
+    Website(usize, usize),
+}
+
+/// fzf-style overlay filtering a list as you type
+struct Finder {
+    scope: FinderScope,
+    input: String,
+    candidates: Vec<(String, Target)>,
+    /// Indices of the candidates matching the input, best first
+    matches: StatefulList<usize>,
+}
+
+impl Finder {
+    fn new(scope: FinderScope, candidates: Vec<(String, Target)>) -> Finder {
+        let mut finder = Finder {
+            scope,
+            input: String::new(),
+            candidates,
+            matches: StatefulList::new(),
+        };
+        finder.filter();
+        finder
+    }
+
+    fn filter(&mut self) {
+        self.matches = StatefulList::with_items(fuzzy::filter(
+            &self.input,
+            self.candidates.iter().map(|(label, _)| label.as_str()),
+        ));
+        self.matches.next();
+    }
+
+    fn selected(&self) -> Option<Target> {
+        self.matches
+            .selected()
+            .map(|&candidate| self.candidates[candidate].1)
+    }
+}
+
+/// A refresh asked for with `r` or `R`, shown in the status bar until every feed answered
+struct Progress {
+    /// URLs of the feeds still being fetched
+    pending: Vec<String>,
+    total: usize,
+    /// Feeds with new articles and how many arrived
+    arrived: Vec<(String, usize)>,
+    /// Errors of the feeds that could not be fetched
+    failed: Vec<String>,
+}
+
+impl Progress {
+    fn new(urls: Vec<String>) -> Progress {
+        Progress {
+            total: urls.len(),
+            pending: urls,
+            arrived: vec![],
+            failed: vec![],
+        }
+    }
+
+    /// Line shown in the status bar once the refresh is over
+    fn summary(&self) -> String {
+        let mut summary = match self.arrived.iter().map(|(_, count)| count).sum::<usize>() {
+            0 => format!("Refreshed {} feeds, no new articles", self.total),
+            new => format!(
+                "Refreshed {} feeds, {} new articles: {}",
+                self.total,
+                new,
+                self.arrived
+                    .iter()
+                    .map(|(name, count)| format!("{} ({})", name, count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        if let Some(error) = self.failed.last() {
+            summary.push_str(&format!(", {} failed ({})", self.failed.len(), error));
+        }
+        summary
+    }
+}
+
+/// An article converted for the reader or the preview, kept while it stays selected as converting
+/// is slow and the screen is drawn every 250 ms
+struct Converted {
+    key: String,
+    content: String,
+    image_rows: u16,
+    /// See `plain_text`
+    text: String,
+    placeholders: Vec<(usize, Image)>,
+    /// Rows the wrapped text takes down to each placeholder, with the width they were counted at
+    above: Option<(u16, Vec<usize>)>,
 }
 
+impl Converted {
+    fn new(article: &Article, image_rows: u16) -> Converted {
+        let (text, placeholders) = plain_text(article, image_rows);
+        Converted {
+            key: article.key(),
+            content: article.content.clone(),
+            image_rows,
+            text,
+            placeholders,
+            above: None,
+        }
+    }
+
+    fn is(&self, article: &Article, image_rows: u16) -> bool {
+        self.image_rows == image_rows
+            && self.key == article.key()
+            && self.content == article.content
+    }
+
+    fn above(&mut self, width: u16) -> &[usize] {
+        if self
+            .above
+            .as_ref()
+            .is_none_or(|(counted, _)| *counted != width)
+        {
+            // every line wraps on its own, so the rows of the lines add up
+            let mut rows = 0;
+            let mut through: Vec<usize> = vec![];
+            for line in self.text.lines() {
+                rows += Paragraph::new(line)
+                    .wrap(Wrap { trim: true })
+                    .line_count(width);
+                through.push(rows);
+            }
+            let above = self
+                .placeholders
+                .iter()
+                .map(|(line, _)| through.get(*line).copied().unwrap_or(rows))
+                .collect();
+            self.above = Some((width, above));
+        }
+        self.above.as_ref().map_or(&[], |(_, above)| above)
+    }
+}
+
+/// A change of the subscriptions asked for by a server, confirmed by the user before it is made
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Change {
+    /// The feed answered with a permanent redirect
+    Move { from: String, to: String },
+    /// The feed answered 410 Gone
+    Remove(String),
+}
+
+/// Style of the text matching a search
+const HIGHLIGHT: Style = Style::new().fg(Color::Black).bg(Color::Yellow);
+
 /// This struct holds the current state of the app. In particular, it has the `items` field which is a wrapper
 /// around `ListState`. Keeping track of the items state let us render the associated widget with its state
 /// and have access to features such as natural scrolling.
@@ -82,29 +329,448 @@ impl<T> StatefulList<T> {
 /// Check the event handling at the bottom to see how to change the state on incoming events.
 /// Check the drawing logic for items on how to specify the highlighting style for selected items.
 pub(crate) struct App {
-    websites: StatefulList<Website>,
+    websites: Vec<Website>,
+    sidebar: StatefulList<Entry>,
     articles: StatefulList<Article>,
+    state: State,
+    /// Virtual feeds of the configuration with their parsed query
+    queries: Vec<(String, Query)>,
+    /// Folders of the configuration with the position of their websites
+    folders: Vec<(String, Vec<usize>)>,
+    sort: Sort,
+    index: Index,
+    /// Query of the last search and the articles it found
+    query: String,
+    results: Vec<Article>,
+    /// Words highlighted in the reader, `n` and `N` jump between them
+    find: Vec<String>,
+    prompt: Option<Prompt>,
+    finder: Option<Finder>,
     scroll: u16,
+    focus: Focus,
+    show_help: bool,
+    layout: config::Layout,
+    /// Fetches the websites again in the background
+    refresher: Refresher,
+    fetcher: Arc<Fetcher>,
+    fetch: config::Fetch,
+    progress: Option<Progress>,
+    /// Articles that arrived with a refresh and were not opened yet
+    new: HashSet<String>,
+    /// Message of the status bar
+    status: String,
+    /// Changes waiting for confirmation, the first one is shown
+    changes: Vec<Change>,
+    /// Changes turned down, not asked again until the next start
+    declined: Vec<Change>,
+    /// Command playing enclosures
+    player: String,
+    /// Enclosure of the article in the reader that `p` plays
+    enclosure: usize,
+    downloader: Downloader,
+    show_downloads: bool,
+    images: Images,
+    /// The selected article as shown, drawing only needs a shared reference
+    converted: RefCell<Option<Converted>>,
+    starred: Starred,
+    /// Where articles are saved as Markdown
+    save: config::Save,
 }
 
 impl App {
-    pub fn new(config: Config) -> App {
-        let mut websites = HashSet::new();
+    /// Runs `f` on the article converted with `image_rows` lines under its images, converting it
+    /// only when another article was converted last
+    fn converted<R>(
+        &self,
+        article: &Article,
+        image_rows: u16,
+        f: impl FnOnce(&mut Converted) -> R,
+    ) -> R {
+        let mut converted = self.converted.borrow_mut();
+        let converted = match converted.as_mut() {
+            Some(found) if found.is(article, image_rows) => found,
+            _ => converted.insert(Converted::new(article, image_rows)),
+        };
+        f(converted)
+    }
 
-        config.subscriptions.iter().for_each(|site| {
-            websites.insert(example_feed(site).unwrap());
-        });
+    pub fn new(config: Config) -> Result<App, Error> {
+        let filter = Arc::new(Filter::new(&config.rules)?);
+        let fetcher = Arc::new(Fetcher::new(&config.fetch)?);
+        let state = state::load()?;
+        let mut websites: Vec<Website> = vec![];
 
-        App {
-            websites: StatefulList::with_items(websites.into_iter().collect::<Vec<_>>()),
+        // the websites are fetched in the background once the app shows up, so that a dead feed
+        // doesn't hold up the start
+        for site in &config.subscriptions {
+            if websites.iter().all(|website| website.url != *site) {
+                websites.push(Website::unreachable(site));
+            }
+        }
+
+        let folders = config
+            .folders
+            .iter()
+            .map(|folder| {
+                let members = websites
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(_, website)| folder.subscriptions.contains(&website.url))
+                    .map(|(index, website)| {
+                        website.tags.push(folder.name.clone());
+                        index
+                    })
+                    .collect();
+                (folder.name.clone(), members)
+            })
+            .collect();
+
+        let queries = config
+            .virtual_feeds
+            .iter()
+            .map(|feed| {
+                Query::parse(&feed.query)
+                    .map(|query| (feed.name.clone(), query))
+                    .map_err(|e| Error::QueryError(format!("{}: {}", feed.name, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let downloader = Downloader::spawn(config.downloads)?;
+        let images = Images::new(config.images)?;
+        let refresher = refresh::spawn(
+            config.refresh.clone(),
+            fetcher.clone(),
+            filter,
+            websites
+                .iter()
+                .filter(|website| !state.paused.contains(&website.url))
+                .map(|website| (website.url.clone(), website.schedule.clone()))
+                .collect(),
+        );
+
+        let mut app = App {
+            index: Index::new(&websites),
+            websites,
+            sidebar: StatefulList::new(),
             articles: StatefulList::new(),
+            state,
+            queries,
+            folders,
+            sort: config.sort,
+            query: String::new(),
+            results: vec![],
+            find: vec![],
+            prompt: None,
+            finder: None,
             scroll: 0,
+            focus: Focus::Websites,
+            show_help: false,
+            layout: config.layout,
+            refresher,
+            fetcher,
+            fetch: config.fetch,
+            progress: None,
+            new: HashSet::new(),
+            status: String::new(),
+            changes: vec![],
+            declined: vec![],
+            player: config.player,
+            enclosure: 0,
+            downloader,
+            show_downloads: false,
+            images,
+            converted: RefCell::new(None),
+            starred: starred::load()?,
+            save: config.save,
+        };
+        app.build_sidebar();
+        // paused feeds wait for a refresh by hand
+        let websites = (0..app.websites.len())
+            .filter(|&index| !app.state.paused.contains(&app.websites[index].url))
+            .collect();
+        app.refresh(websites);
+
+        Ok(app)
+    }
+
+    /// Unread articles of some websites
+    fn unread(&self, websites: &[usize]) -> usize {
+        websites
+            .iter()
+            .flat_map(|&index| self.websites[index].articles.iter())
+            .filter(|article| !self.state.is_read(article))
+            .count()
+    }
+
+    /// Most recent article of some websites
+    fn updated(&self, websites: &[usize]) -> Option<jiff::Timestamp> {
+        websites
+            .iter()
+            .flat_map(|&index| self.websites[index].articles.iter())
+            .filter_map(|article| article.published())
+            .max()
+    }
+
+    /// Orders two folders or websites (a folder of one) by the configured sort, the manual sort
+    /// keeps the order of the configuration since sorting is stable
+    fn compare(&self, (a_name, a): (&str, &[usize]), (b_name, b): (&str, &[usize])) -> Ordering {
+        let by_name = || a_name.to_lowercase().cmp(&b_name.to_lowercase());
+
+        match self.sort {
+            Sort::Manual => Ordering::Equal,
+            Sort::Name => by_name(),
+            Sort::Unread => self.unread(b).cmp(&self.unread(a)).then_with(by_name),
+            Sort::Updated => self.updated(b).cmp(&self.updated(a)).then_with(by_name),
+        }
+    }
+
+    fn sorted_websites(&self, mut websites: Vec<usize>) -> Vec<usize> {
+        websites.sort_by(|&a, &b| {
+            self.compare(
+                (&self.websites[a].name, &[a]),
+                (&self.websites[b].name, &[b]),
+            )
+        });
+        websites
+    }
+
+    /// Lists the virtual feeds, then the folders with their websites and the websites that are
+    /// in no folder, keeping the selected entry selected
+    fn build_sidebar(&mut self) {
+        let selected = self.sidebar.selected().copied();
+
+        let mut entries: Vec<Entry> = Timeline::ALL.into_iter().map(Entry::Timeline).collect();
+        entries.push(Entry::Starred);
+        if self.sidebar.items.contains(&Entry::Search) {
+            entries.push(Entry::Search);
+        }
+        entries.extend((0..self.queries.len()).map(Entry::Query));
+
+        let mut folders: Vec<usize> = (0..self.folders.len()).collect();
+        folders.sort_by(|&a, &b| {
+            let (a_name, a_websites) = &self.folders[a];
+            let (b_name, b_websites) = &self.folders[b];
+            self.compare((a_name, a_websites), (b_name, b_websites))
+        });
+        for folder in folders {
+            entries.push(Entry::Folder(folder));
+            let (name, websites) = &self.folders[folder];
+            if !self.state.collapsed.contains(name) {
+                entries.extend(
+                    self.sorted_websites(websites.clone())
+                        .into_iter()
+                        .map(|index| Entry::Website {
+                            index,
+                            folder: Some(folder),
+                        }),
+                );
+            }
+        }
+
+        let unfiled = (0..self.websites.len())
+            .filter(|index| {
+                self.folders
+                    .iter()
+                    .all(|(_, websites)| !websites.contains(index))
+            })
+            .collect();
+        entries.extend(
+            self.sorted_websites(unfiled)
+                .into_iter()
+                .map(|index| Entry::Website {
+                    index,
+                    folder: None,
+                }),
+        );
+
+        self.sidebar.items = entries;
+        self.sidebar
+            .state
+            .select(selected.and_then(|entry| self.sidebar.items.iter().position(|e| *e == entry)));
+    }
+
+    /// Collapses or expands the selected folder
+    fn toggle_folder(&mut self) {
+        if let Some(Entry::Folder(folder)) = self.sidebar.selected() {
+            let name = &self.folders[*folder].0;
+            if !self.state.collapsed.remove(name) {
+                self.state.collapsed.insert(name.clone());
+            }
+            state::store(&self.state).unwrap();
+            self.build_sidebar();
         }
     }
 
     fn load_articles(&mut self) {
-        if let Some(index) = self.websites.state.selected() {
-            self.articles = StatefulList::with_items(self.websites.items[index].articles.clone());
+        let articles = match self.sidebar.selected() {
+            Some(Entry::Timeline(timeline)) => timeline.articles(&self.websites, &self.state),
+            Some(Entry::Starred) => self.starred.articles.clone(),
+            Some(Entry::Search) => self.results.clone(),
+            Some(Entry::Query(index)) => {
+                self.queries[*index].1.articles(&self.websites, &self.state)
+            }
+            Some(Entry::Folder(folder)) => {
+                let mut articles: Vec<Article> = self.folders[*folder]
+                    .1
+                    .iter()
+                    .flat_map(|&index| self.websites[index].articles.iter().cloned())
+                    .collect();
+                articles.sort_by_cached_key(|article| Reverse(article.published()));
+                articles
+            }
+            Some(Entry::Website { index, .. }) => self.websites[*index].articles.clone(),
+            None => return,
+        };
+        self.articles = StatefulList::with_items(articles);
+    }
+
+    /// Name shown in the sidebar for an entry
+    fn entry_name(&self, entry: &Entry) -> String {
+        match entry {
+            Entry::Timeline(timeline) => timeline.name().to_string(),
+            Entry::Starred => "Starred".to_string(),
+            Entry::Search => format!("Search: {}", self.query),
+            Entry::Query(index) => self.queries[*index].0.clone(),
+            Entry::Folder(folder) => self.folders[*folder].0.clone(),
+            Entry::Website { index, .. } => self.websites[*index].name.clone(),
+        }
+    }
+
+    /// Words of the last search when its results are shown
+    fn search_terms(&self) -> Vec<String> {
+        match self.sidebar.selected() {
+            Some(Entry::Search) => search::terms(&self.query).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Runs a search over every article and shows the results as a virtual feed
+    fn search(&mut self, query: String) {
+        self.results = self.index.search(&self.websites, &query);
+        self.query = query;
+
+        let position = match self.sidebar.items.iter().position(|e| *e == Entry::Search) {
+            Some(position) => position,
+            None => {
+                // results go right below the timelines
+                let position = Timeline::ALL.len();
+                self.sidebar.items.insert(position, Entry::Search);
+                position
+            }
+        };
+        self.sidebar.state.select(Some(position));
+        self.load_articles();
+
+        self.articles.next();
+        self.focus = match self.articles.selected() {
+            Some(_) => Focus::Articles,
+            None => Focus::Websites,
+        };
+    }
+
+    /// Scroll positions of the reader lines matching the find terms, approximated like
+    /// `scroll_down` from the terminal width
+    fn find_lines(&self, width: u16) -> Vec<u16> {
+        let Some(article) = self.articles.selected() else {
+            return vec![];
+        };
+
+        // the reader has a margin and a border on both sides
+        let columns = width.saturating_sub(12).max(1) as usize;
+        let mut offset = 0;
+        let mut found = vec![];
+        self.converted(article, self.images.rows(), |converted| {
+            for line in converted.text.lines() {
+                if !search::matches(line, &self.find).is_empty() {
+                    found.push(offset);
+                }
+                offset += line.width().div_ceil(columns).max(1) as u16;
+            }
+        });
+
+        found
+    }
+
+    /// Scrolls to the next match after the top of the reader, wrapping around at the end
+    fn find_next(&mut self, width: u16) {
+        let found = self.find_lines(width);
+        if let Some(line) = found
+            .iter()
+            .find(|&&line| line > self.scroll)
+            .or(found.first())
+        {
+            self.scroll = *line;
+        }
+    }
+
+    /// Scrolls to the previous match before the top of the reader, wrapping around at the start
+    fn find_previous(&mut self, width: u16) {
+        let found = self.find_lines(width);
+        if let Some(line) = found
+            .iter()
+            .rev()
+            .find(|&&line| line < self.scroll)
+            .or(found.last())
+        {
+            self.scroll = *line;
+        }
+    }
+
+    fn submit(&mut self, prompt: Prompt, width: u16) {
+        match prompt.kind {
+            PromptKind::Search => self.search(prompt.input),
+            PromptKind::Export => {
+                let path = export::expand(&prompt.input);
+                self.status = match export::write("Starred articles", &self.starred.articles, &path)
+                {
+                    Ok(()) => format!(
+                        "Exported {} starred articles to {}",
+                        self.starred.articles.len(),
+                        path.display()
+                    ),
+                    Err(error) => error.to_string(),
+                };
+            }
+            PromptKind::Epub => {
+                let path = export::expand(&prompt.input);
+                let title = self
+                    .sidebar
+                    .selected()
+                    .map(|entry| self.entry_name(entry))
+                    .unwrap_or_else(|| "trss".to_string());
+                let sections = epub::sections(&self.articles.items);
+                self.status = match epub::write(&title, &sections, &path) {
+                    Ok(()) => format!(
+                        "Packed {} articles into {}",
+                        self.articles.items.len(),
+                        path.display()
+                    ),
+                    Err(error) => error.to_string(),
+                };
+            }
+            PromptKind::Find => {
+                self.find = search::terms(&prompt.input).collect();
+                // a match on the first visible line counts as the next one
+                if let Some(line) = self
+                    .find_lines(width)
+                    .into_iter()
+                    .find(|&line| line >= self.scroll)
+                {
+                    self.scroll = line;
+                }
+            }
+        }
+    }
+
+    /// Flips the read flag of the selected article
+    fn toggle_read(&mut self) {
+        if let Some(article) = self.articles.selected() {
+            if self.state.is_read(article) {
+                self.state.mark_unread(article);
+            } else {
+                self.state.mark_read(article);
+            }
+            state::store(&self.state).unwrap();
         }
     }
 
@@ -113,26 +779,23 @@ impl App {
     }
 
     fn scroll_down(&mut self, width: u16) {
-        if let Some(index) = self.articles.state.selected() {
-            // move this elsewhere you are doing it twice (here and in read UI)
-            let raw_html = self.articles.items[index].content.clone();
-            let parsed_to_markdown = html2md::parse_html(&raw_html);
-            let markdown_to_terminal = termimad::inline(&parsed_to_markdown).to_string();
+        if let Some(article) = self.articles.selected() {
+            let (markdown_to_terminal, placeholders) = article_text(article);
 
             let content_length_md =
                 markdown_to_terminal.chars().count() / (width as f32 * 0.57) as usize;
             let new_line_count_md = markdown_to_terminal
                 .lines()
-                .filter(|l| *l == "\n" || *l == "" || l.is_empty())
-                .collect::<Vec<&str>>()
-                .len();
+                .filter(|l| l.is_empty())
+                .count();
+
+            // the room left for images is known exactly
+            let image_lines = placeholders.len() * self.images.rows() as usize;
 
             // try to find a healthy balance between the lines and scroll
-            let content_length = content_length_md + new_line_count_md;
+            let content_length = content_length_md + new_line_count_md + image_lines;
             if Into::<usize>::into(self.scroll) < content_length {
                 self.scroll += 1;
-            } else {
-                self.scroll += 0;
             }
         } else {
             self.scroll += 1;
@@ -151,17 +814,657 @@ impl App {
         self.scroll = 0;
     }
 
-    fn on_tick(&mut self) {}
+    /// Selects the next enclosure of the article in the reader, back to the first after the last
+    fn next_enclosure(&mut self) {
+        if let Some(article) = self.articles.selected() {
+            if !article.enclosures.is_empty() {
+                self.enclosure = (self.enclosure + 1) % article.enclosures.len();
+            }
+        }
+    }
+
+    /// Hands the selected enclosure to the player
+    fn play(&mut self) {
+        let Some(enclosure) = self
+            .articles
+            .selected()
+            .and_then(|article| article.enclosures.get(self.enclosure))
+        else {
+            self.status = "This article has no enclosure".to_string();
+            return;
+        };
+
+        self.status = match enclosure::play(&self.player, enclosure) {
+            Ok(()) => format!("Playing {}", enclosure.url),
+            Err(error) => format!("Could not start \"{}\": {}", self.player, error),
+        };
+    }
+
+    /// Fetches the websites of the selected entry right away, or every website for the
+    /// timelines, searches and virtual feeds
+    fn refresh_selected(&mut self) {
+        let websites = match self.sidebar.selected() {
+            Some(Entry::Website { index, .. }) => vec![*index],
+            Some(Entry::Folder(folder)) => self.folders[*folder].1.clone(),
+            _ => (0..self.websites.len()).collect(),
+        };
+        self.refresh(websites);
+    }
+
+    fn refresh(&mut self, websites: Vec<usize>) {
+        if self.progress.is_some() || websites.is_empty() {
+            return;
+        }
+
+        let urls: Vec<String> = websites
+            .iter()
+            .map(|&index| self.websites[index].url.clone())
+            .collect();
+        self.refresher.refresh(urls.clone());
+        self.progress = Some(Progress::new(urls));
+    }
+
+    /// Merges the websites refreshed in the background
+    fn on_tick(&mut self) {
+        self.images.update();
+
+        let mut refreshed = false;
+        while let Ok(update) = self.refresher.updates.try_recv() {
+            let (url, requested, arrived) = match update {
+                Update::Fetched { website, requested } => {
+                    let url = website.url.clone();
+                    if self.state.record_success(&url) {
+                        state::store(&self.state).unwrap();
+                    }
+                    if let Some(to) = website.moved.clone() {
+                        self.propose(Change::Move {
+                            from: url.clone(),
+                            to,
+                        });
+                    }
+                    let name = website.name.clone();
+                    let count = self.merge(*website);
+                    self.auto_download(&url);
+                    refreshed = true;
+                    (url, requested, Ok((name, count)))
+                }
+                Update::Failed {
+                    url,
+                    error,
+                    gone,
+                    requested,
+                } => {
+                    self.failed(&url, error.clone(), gone);
+                    let error = format!("{}: {}", url, error);
+                    (url, requested, Err(error))
+                }
+            };
+
+            match self.progress.as_mut() {
+                // a scheduled fetch of a pending feed may have started before the refresh
+                Some(_) if !requested => {}
+                Some(progress) => {
+                    if let Some(position) = progress.pending.iter().position(|p| *p == url) {
+                        progress.pending.remove(position);
+                        match arrived {
+                            Ok((name, count)) if count > 0 => progress.arrived.push((name, count)),
+                            Ok(_) => {}
+                            Err(error) => progress.failed.push(error),
+                        }
+                    }
+                    if progress.pending.is_empty() {
+                        self.status = progress.summary();
+                        self.progress = None;
+                    }
+                }
+                // scheduled refreshes only speak up when something arrived
+                None => {
+                    if let Some((name, count)) = arrived.ok().filter(|(_, count)| *count > 0) {
+                        self.status = format!("{}: {} new articles", name, count);
+                    }
+                }
+            }
+        }
+
+        let finished = self.downloader.update();
+        if let Some(last) = finished.last() {
+            self.status = format!("Downloaded {}", last.path.display());
+            for download in finished {
+                self.state.downloaded.insert(download.url);
+            }
+            state::store(&self.state).unwrap();
+        }
+
+        if refreshed {
+            self.index = Index::new(&self.websites);
+            self.build_sidebar();
+            self.reload_articles();
+        }
+    }
+
+    /// Stars the selected article or takes its star away, starred articles are kept even once
+    /// they are gone from their feed
+    fn toggle_star(&mut self) {
+        let Some(article) = self.articles.selected() else {
+            return;
+        };
+        self.status = if self.starred.toggle(article) {
+            format!("Starred {}", article.title)
+        } else {
+            format!("Unstarred {}", article.title)
+        };
+        starred::store(&self.starred).unwrap();
+
+        if self.sidebar.selected() == Some(&Entry::Starred) {
+            self.reload_articles();
+        }
+    }
+
+    /// Saves the selected article as Markdown
+    fn save_article(&mut self) {
+        let Some(article) = self.articles.selected() else {
+            return;
+        };
+        self.status = match export::save(&self.save, article) {
+            Ok(path) => format!("Saved {}", path.display()),
+            Err(error) => error.to_string(),
+        };
+    }
+
+    /// Saves every article of the list, the whole feed, folder, search or virtual feed
+    fn save_articles(&mut self) {
+        // an article that cannot be saved doesn't stop the others
+        let failed: Vec<Error> = self
+            .articles
+            .items
+            .iter()
+            .filter_map(|article| export::save(&self.save, article).err())
+            .collect();
+        let saved = self.articles.items.len() - failed.len();
+        self.status = format!("Saved {} articles to {}", saved, self.save.directory);
+        if let Some(error) = failed.last() {
+            self.status
+                .push_str(&format!(", {} failed ({})", failed.len(), error));
+        }
+    }
+
+    /// Queues the newest episodes of a subscription that downloads them automatically, the ones
+    /// downloaded before are skipped even when their file was deleted since
+    fn auto_download(&mut self, url: &str) {
+        let newest = self.downloader.auto(url);
+        let Some(website) = self.websites.iter().find(|website| website.url == url) else {
+            return;
+        };
+
+        let mut episodes: Vec<&Article> = website
+            .articles
+            .iter()
+            .filter(|article| !article.enclosures.is_empty())
+            .collect();
+        episodes.sort_by_key(|article| Reverse(article.published()));
+        for article in episodes.into_iter().take(newest) {
+            let enclosure = &article.enclosures[0];
+            if !self.state.downloaded.contains(&enclosure.url) {
+                self.downloader.queue(article, enclosure);
+            }
+        }
+    }
+
+    /// Queues the selected enclosure of the article in the reader, or the first enclosure of the
+    /// selected article
+    fn download(&mut self) {
+        let index = match self.focus {
+            Focus::Reader => self.enclosure,
+            _ => 0,
+        };
+        let Some(article) = self.articles.selected() else {
+            return;
+        };
+        let Some(enclosure) = article.enclosures.get(index) else {
+            self.status = "This article has no enclosure".to_string();
+            return;
+        };
+
+        self.status = if self.downloader.queue(article, enclosure) {
+            format!(
+                "Downloading {} ({} in progress, D shows them)",
+                enclosure.url,
+                self.downloader.active()
+            )
+        } else {
+            format!("{} is already in the downloads", enclosure.url)
+        };
+    }
+
+    /// Counts a failed fetch, pausing the feed when it is dead and the configuration says so
+    fn failed(&mut self, url: &str, error: String, gone: bool) {
+        self.state.record_failure(url, error, gone);
+        if gone {
+            self.propose(Change::Remove(url.to_string()));
+        }
+        // a paused feed refreshed by hand is scheduled again, pause it again if it still fails
+        if self.fetch.pause_dead && self.state.is_dead(url, self.fetch.dead_after_days) {
+            self.state.paused.insert(url.to_string());
+            self.refresher.pause(url.to_string());
+        }
+        state::store(&self.state).unwrap();
+    }
+
+    /// Flag shown next to a website that keeps failing
+    fn health(&self, url: &str) -> Option<String> {
+        if self
+            .state
+            .failures
+            .get(url)
+            .is_some_and(|failure| failure.gone)
+        {
+            Some("gone".to_string())
+        } else if self.state.paused.contains(url) {
+            Some("paused".to_string())
+        } else if self.state.is_dead(url, self.fetch.dead_after_days) {
+            Some("dead".to_string())
+        } else {
+            let failure = self.state.failures.get(url)?;
+            Some(format!("{} failures", failure.count))
+        }
+    }
+
+    /// Makes a change right away when the configuration allows it, otherwise asks first
+    fn propose(&mut self, change: Change) {
+        if self.changes.contains(&change) || self.declined.contains(&change) {
+            return;
+        }
+        match &change {
+            // another subscription already points there
+            Change::Move { to, .. } if self.websites.iter().any(|w| w.url == *to) => {}
+            Change::Move { .. } if self.fetch.update_moved => self.apply(change),
+            _ => self.changes.push(change),
+        }
+    }
+
+    /// Writes a change to the configuration and to the websites shown
+    fn apply(&mut self, change: Change) {
+        let result = match &change {
+            Change::Move { from, to } => config::move_subscription(from, to),
+            Change::Remove(url) => config::remove_subscription(url),
+        };
+        if let Err(error) = result {
+            self.status = error.to_string();
+            return;
+        }
+
+        match change {
+            Change::Move { from, to } => {
+                if let Some(website) = self.websites.iter_mut().find(|w| w.url == from) {
+                    website.url = to.clone();
+                }
+                self.state.forget(&from);
+                self.refresher.pause(from.clone());
+                self.refresher.refresh(vec![to.clone()]);
+                self.status = format!("{} moved to {}", from, to);
+            }
+            Change::Remove(url) => {
+                if let Some(index) = self.websites.iter().position(|w| w.url == url) {
+                    self.websites.remove(index);
+                    // the positions after the removed website move up by one
+                    for (_, websites) in self.folders.iter_mut() {
+                        websites.retain(|&website| website != index);
+                        for website in websites.iter_mut().filter(|w| **w > index) {
+                            *website -= 1;
+                        }
+                    }
+                }
+                self.state.forget(&url);
+                self.refresher.pause(url.clone());
+                self.index = Index::new(&self.websites);
+                self.sidebar.unselect();
+                self.clear_articles();
+                self.focus = Focus::Websites;
+                self.build_sidebar();
+                self.status = format!("Removed {}", url);
+            }
+        }
+        state::store(&self.state).unwrap();
+    }
+
+    /// Replaces a website by its refreshed version, keeping the folders it is in, returns how
+    /// many articles are new
+    fn merge(&mut self, mut website: Website) -> usize {
+        let Some(old) = self.websites.iter_mut().find(|w| w.url == website.url) else {
+            return 0;
+        };
+
+        // a website that could not be fetched before has nothing to compare with
+        let mut count = 0;
+        let articles = if old.articles.is_empty() {
+            &[][..]
+        } else {
+            &website.articles[..]
+        };
+        for article in articles {
+            if old.articles.iter().all(|a| a.id != article.id) {
+                self.new.insert(article.id.clone());
+                count += 1;
+            }
+        }
+
+        website.tags = std::mem::take(&mut old.tags);
+        *old = website;
+        count
+    }
+
+    /// Loads the articles again after a refresh, keeping the selected article and the position
+    /// of the list
+    fn reload_articles(&mut self) {
+        if self.sidebar.selected().is_none() {
+            return;
+        }
+
+        let selected = self.articles.selected().map(|article| article.id.clone());
+        let offset = self.articles.state.offset();
+        let previous = std::mem::replace(&mut self.articles, StatefulList::new());
+        self.load_articles();
+
+        if let Some(id) = selected {
+            match self
+                .articles
+                .items
+                .iter()
+                .position(|article| article.id == id)
+            {
+                Some(position) => self.articles.state.select(Some(position)),
+                // the selected article is gone from the list, keep the old one until the user
+                // moves on
+                None => {
+                    self.articles = previous;
+                    return;
+                }
+            }
+        }
+        *self.articles.state.offset_mut() = offset;
+    }
+
+    fn open_finder(&mut self, scope: FinderScope) {
+        let candidates = match scope {
+            FinderScope::Sidebar => self
+                .sidebar
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| (self.entry_name(entry), Target::Entry(i)))
+                .collect(),
+            FinderScope::Articles => self
+                .articles
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, article)| (article.title.clone(), Target::Article(i)))
+                .collect(),
+            FinderScope::Everywhere => self
+                .websites
+                .iter()
+                .enumerate()
+                .flat_map(|(w, website)| {
+                    website
+                        .articles
+                        .iter()
+                        .enumerate()
+                        .map(move |(a, article)| {
+                            (
+                                format!("{} ({})", article.title, website.name),
+                                Target::Website(w, a),
+                            )
+                        })
+                })
+                .collect(),
+        };
+
+        self.finder = Some(Finder::new(scope, candidates));
+    }
+
+    /// Selects the entry chosen in the finder
+    fn jump(&mut self, target: Target) {
+        match target {
+            Target::Entry(i) => {
+                self.sidebar.state.select(Some(i));
+                self.load_articles();
+                self.focus = Focus::Websites;
+            }
+            Target::Article(i) => {
+                self.articles.state.select(Some(i));
+                self.focus = Focus::Articles;
+            }
+            Target::Website(w, a) => {
+                let position = |sidebar: &StatefulList<Entry>| {
+                    sidebar.items.iter().position(
+                        |entry| matches!(entry, Entry::Website { index, .. } if *index == w),
+                    )
+                };
+                if position(&self.sidebar).is_none() {
+                    // the website is hidden in collapsed folders
+                    for (name, websites) in &self.folders {
+                        if websites.contains(&w) {
+                            self.state.collapsed.remove(name);
+                        }
+                    }
+                    self.build_sidebar();
+                }
+                self.sidebar.state.select(position(&self.sidebar));
+                // the list is sorted and filtered, the article is rarely at the same position
+                let key = self.websites[w].articles.get(a).map(Article::key);
+                self.load_articles();
+                let found = self
+                    .articles
+                    .items
+                    .iter()
+                    .position(|article| Some(article.key()) == key);
+                if found.is_some() {
+                    self.articles.state.select(found);
+                }
+                self.focus = Focus::Articles;
+            }
+        }
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            input: String::new(),
+        });
+    }
+
+    /// Handles a key press for the focused panel, returns `true` when the app should exit
+    fn on_key(&mut self, code: KeyCode, width: u16) -> bool {
+        if let Some(prompt) = self.prompt.as_mut() {
+            match code {
+                KeyCode::Char(c) => prompt.input.push(c),
+                KeyCode::Backspace => {
+                    prompt.input.pop();
+                }
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Enter => {
+                    if let Some(prompt) = self.prompt.take() {
+                        self.submit(prompt, width);
+                    }
+                }
+                _ => {}
+            }
+            return false;
+        }
+
+        if let Some(finder) = self.finder.as_mut() {
+            match code {
+                KeyCode::Char(c) => {
+                    finder.input.push(c);
+                    finder.filter();
+                }
+                KeyCode::Backspace => {
+                    finder.input.pop();
+                    finder.filter();
+                }
+                KeyCode::Down => finder.matches.next(),
+                KeyCode::Up => finder.matches.previous(),
+                KeyCode::Esc => self.finder = None,
+                KeyCode::Enter => {
+                    if let Some(target) = self.finder.take().and_then(|f| f.selected()) {
+                        self.jump(target);
+                    }
+                }
+                _ => {}
+            }
+            return false;
+        }
+
+        if let Some(change) = self.changes.first().cloned() {
+            match code {
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    self.changes.remove(0);
+                    self.apply(change);
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.changes.remove(0);
+                    self.declined.push(change);
+                }
+                _ => {}
+            }
+            return false;
+        }
+
+        if self.show_help {
+            if let KeyCode::Char('q') | KeyCode::Esc = code {
+                self.show_help = false;
+            }
+            return false;
+        }
+
+        if self.show_downloads {
+            if let KeyCode::Char('q') | KeyCode::Char('D') | KeyCode::Esc = code {
+                self.show_downloads = false;
+            }
+            return false;
+        }
+
+        match self.focus {
+            Focus::Websites => match code {
+                KeyCode::Char('a') => {
+                    let sites = input::main(&self.fetcher).unwrap();
+                    for site in sites {
+                        crate::config::update_or_store(site.clone()).unwrap();
+                    }
+                }
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    self.sidebar.unselect();
+                    return true;
+                }
+                KeyCode::Left => {
+                    self.clear_articles();
+                    self.sidebar.unselect()
+                }
+                KeyCode::Down => {
+                    self.sidebar.next();
+                    self.load_articles()
+                }
+                KeyCode::Up => {
+                    self.sidebar.previous();
+                    self.load_articles()
+                }
+                KeyCode::Char('h') => self.show_help = true,
+                KeyCode::Char('D') => self.show_downloads = true,
+                KeyCode::Char('/') => self.open_prompt(PromptKind::Search),
+                KeyCode::Char('f') => self.open_finder(FinderScope::Sidebar),
+                KeyCode::Char('F') => self.open_finder(FinderScope::Everywhere),
+                KeyCode::Char(' ') => self.toggle_folder(),
+                KeyCode::Char('x') => self.open_prompt(PromptKind::Export),
+                KeyCode::Char('b') => self.open_prompt(PromptKind::Epub),
+                KeyCode::Char('r') => self.refresh_selected(),
+                KeyCode::Char('R') => self.refresh((0..self.websites.len()).collect()),
+                KeyCode::Right | KeyCode::Enter => {
+                    self.articles.next();
+                    if self.articles.selected().is_some() {
+                        self.focus = Focus::Articles;
+                    }
+                }
+                // all other keys do nothing
+                _ => {}
+            },
+            Focus::Articles => match code {
+                KeyCode::Left | KeyCode::Char('q') | KeyCode::Esc => {
+                    self.articles.unselect();
+                    self.focus = Focus::Websites;
+                }
+                KeyCode::Down => self.articles.next(),
+                KeyCode::Up => self.articles.previous(),
+                KeyCode::Char('h') => self.show_help = true,
+                KeyCode::Char('D') => self.show_downloads = true,
+                KeyCode::Char('m') => self.toggle_read(),
+                KeyCode::Char('/') => self.open_prompt(PromptKind::Search),
+                KeyCode::Char('f') => self.open_finder(FinderScope::Articles),
+                KeyCode::Char('F') => self.open_finder(FinderScope::Everywhere),
+                KeyCode::Char('r') => self.refresh_selected(),
+                KeyCode::Char('R') => self.refresh((0..self.websites.len()).collect()),
+                KeyCode::Char('d') => self.download(),
+                KeyCode::Char('s') => self.toggle_star(),
+                KeyCode::Char('x') => self.open_prompt(PromptKind::Export),
+                KeyCode::Char('w') => self.save_article(),
+                KeyCode::Char('W') => self.save_articles(),
+                KeyCode::Char('b') => self.open_prompt(PromptKind::Epub),
+                KeyCode::Right | KeyCode::Enter => {
+                    if let Some(article) = self.articles.selected() {
+                        self.new.remove(&article.id);
+                        self.state.mark_read(article);
+                        state::store(&self.state).unwrap();
+                        self.find = self.search_terms();
+                        self.enclosure = 0;
+                        self.reset_scroll();
+                        self.focus = Focus::Reader;
+                    }
+                }
+                _ => {}
+            },
+            Focus::Reader => match code {
+                KeyCode::Up => self.scroll_up(),
+                // Used to determine how far we can scroll
+                // scroll down does the content characters by width to get a rough
+                // idea of how many lines there will be
+                KeyCode::Down => self.scroll_down(width),
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    self.reset_scroll();
+                    self.focus = Focus::Articles;
+                }
+                KeyCode::Char('h') => self.show_help = true,
+                KeyCode::Char('D') => self.show_downloads = true,
+                KeyCode::Char('/') => self.open_prompt(PromptKind::Find),
+                KeyCode::Char('n') => self.find_next(width),
+                KeyCode::Char('N') => self.find_previous(width),
+                KeyCode::Char('e') => self.next_enclosure(),
+                KeyCode::Char('p') => self.play(),
+                KeyCode::Char('d') => self.download(),
+                KeyCode::Char('s') => self.toggle_star(),
+                KeyCode::Char('w') => self.save_article(),
+                _ => {}
+            },
+        }
+
+        false
+    }
 }
 
-pub(crate) fn run_app<B: Backend>(
+pub(crate) fn run_app<B: Backend + io::Write>(
     terminal: &mut Terminal<B>,
     mut app: App,
     tick_rate: Duration,
 ) -> io::Result<()> {
     let mut last_tick = Instant::now();
     loop {
-        terminal.draw(|f| ui(f, &mut app))?;
+        terminal.draw(|f| draw(f, &mut app))?;
+        // images drawn with escape sequences go on top of the frame
+        if app.images.moved() {
+            if app.images.needs_clear() {
+                terminal.clear()?;
+                terminal.draw(|f| draw(f, &mut app))?;
+            }
+            app.images.show(terminal.backend_mut())?;
+        }
 
         let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
@@ -169,139 +1472,9 @@ pub(crate) fn run_app<B: Backend>(
 
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('a') => {
-                        let sites = input::main().unwrap();
-                        for site in sites {
-                            crate::config::update_or_store(site.clone()).unwrap();
-                        }
-                    }
-                    KeyCode::Char('q') | KeyCode::Esc => {
-                        app.websites.unselect();
-                        return Ok(());
-                    }
-                    KeyCode::Left => {
-                        app.clear_articles();
-                        app.websites.unselect()
-                    }
-                    KeyCode::Down => {
-                        app.websites.next();
-                        app.load_articles()
-                    }
-                    KeyCode::Up => {
-                        app.websites.previous();
-                        app.load_articles()
-                    }
-                    KeyCode::Char('h') => 'help_loop: loop {
-                        terminal.draw(|f| help_ui(f))?;
-
-                        if crossterm::event::poll(timeout)? {
-                            if let Event::Key(key) = event::read()? {
-                                match key.code {
-                                    KeyCode::Char('q') | KeyCode::Esc => {
-                                        break 'help_loop;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                    },
-                    KeyCode::Right | KeyCode::Enter => {
-                        app.articles.next();
-                        'article_select: loop {
-                            terminal.draw(|f| ui(f, &mut app))?;
-                            if let Event::Key(key) = event::read()? {
-                                match key.code {
-                                    KeyCode::Left => {
-                                        app.articles.unselect();
-                                        break 'article_select;
-                                    }
-                                    KeyCode::Down => app.articles.next(),
-                                    KeyCode::Up => app.articles.previous(),
-                                    KeyCode::Char('q') | KeyCode::Esc => {
-                                        app.articles.unselect();
-                                        break 'article_select;
-                                    }
-                                    KeyCode::Char('h') => 'help_loop: loop {
-                                        terminal.draw(|f| help_ui(f))?;
-
-                                        if crossterm::event::poll(timeout)? {
-                                            if let Event::Key(key) = event::read()? {
-                                                match key.code {
-                                                    KeyCode::Char('q') | KeyCode::Esc => {
-                                                        break 'help_loop;
-                                                    }
-                                                    _ => {}
-                                                }
-                                            }
-                                        }
-                                    },
-                                    KeyCode::Right | KeyCode::Enter => {
-                                        if let Some(article) = app.articles.state.selected() {
-                                            'read: loop {
-                                                terminal.draw(|f| {
-                                                    read_ui::<B>(
-                                                        f,
-                                                        &app,
-                                                        &app.articles.items[article],
-                                                    )
-                                                })?;
-                                                // Used to determine how far we can scroll
-                                                // scroll down does the content characters by width to get a rough
-                                                // idea of how many lines there will be
-                                                let width = terminal.get_frame().area().width;
-
-                                                let timeout = tick_rate
-                                                    .checked_sub(last_tick.elapsed())
-                                                    .unwrap_or_else(|| Duration::from_secs(0));
-                                                if crossterm::event::poll(timeout)? {
-                                                    if let Event::Key(key) = event::read()? {
-                                                        match key.code {
-                                                            KeyCode::Up => app.scroll_up(),
-                                                            KeyCode::Down => app.scroll_down(width),
-                                                            KeyCode::Esc | KeyCode::Char('q') => {
-                                                                app.reset_scroll();
-                                                                break 'read;
-                                                            }
-                                                            KeyCode::Char('h') => {
-                                                                'help_loop: loop {
-                                                                    terminal
-                                                                        .draw(|f| help_ui(f))?;
-
-                                                                    if crossterm::event::poll(
-                                                                        timeout,
-                                                                    )? {
-                                                                        if let Event::Key(key) =
-                                                                            event::read()?
-                                                                        {
-                                                                            match key.code {
-                                                                                KeyCode::Char(
-                                                                                    'q',
-                                                                                )
-                                                                                | KeyCode::Esc => {
-                                                                                    break 'help_loop;
-                                                                                }
-                                                                                _ => {}
-                                                                            }
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                            _ => {}
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        } else {
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                    }
-                    // all other keys do nothing
-                    _ => {}
+                let width = terminal.size()?.width;
+                if app.on_key(key.code, width) {
+                    return Ok(());
                 }
             }
         }
@@ -313,9 +1486,166 @@ pub(crate) fn run_app<B: Backend>(
     }
 }
 
-fn read_ui<B: Backend>(f: &mut Frame, app: &App, article: &Article) {
-    let area = f.area();
+fn draw(f: &mut Frame, app: &mut App) {
+    app.images.clear();
+    if app.show_help {
+        help_ui(f);
+        return;
+    }
+
+    let [main, status] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(f.area());
+
+    if app.show_downloads {
+        downloads_ui(f, app, main);
+        status_ui(f, app, status);
+        return;
+    }
+
+    match app.focus {
+        Focus::Reader => {
+            if let Some(article) = app.articles.selected() {
+                read_ui(f, app, article, main)
+            }
+        }
+        Focus::Websites | Focus::Articles => ui(f, app, main),
+    }
+    status_ui(f, app, status);
+
+    if let Some(finder) = app.finder.as_mut() {
+        finder_ui(f, finder);
+    }
+
+    if let Some(prompt) = &app.prompt {
+        prompt_ui(f, prompt);
+    } else if let Some(change) = app.changes.first() {
+        confirm_ui(f, change);
+    }
+}
+
+/// Converts the HTML content of an article to text ready for the terminal, images become
+/// numbered placeholders, returned with their line
+fn article_text(article: &Article) -> (String, Vec<(usize, Image)>) {
+    let extracted = images::extract(&article.content);
+    let parsed_to_markdown = html2md::parse_html(&extracted.html);
+    extracted.caption(&termimad::inline(&parsed_to_markdown).to_string())
+}
+
+/// Article text without the terminal styling, as shown in the reader, with `image_rows` empty
+/// lines under every image placeholder
+fn plain_text(article: &Article, image_rows: u16) -> (String, Vec<(usize, Image)>) {
+    let (text, placeholders) = article_text(article);
+    let text = text.into_text().unwrap().to_string();
+    if image_rows == 0 {
+        return (text, placeholders);
+    }
+
+    let lines: BTreeSet<usize> = placeholders.iter().map(|(line, _)| *line).collect();
+    let room = "\n".repeat(image_rows as usize);
+    let text = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| match lines.contains(&index) {
+            true => format!("{}{}", line, room),
+            false => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    // a placeholder moves down by the room left under the ones above it
+    let placeholders = placeholders
+        .into_iter()
+        .map(|(line, image)| {
+            let above = lines.range(..line).count();
+            (line + above * image_rows as usize, image)
+        })
+        .collect();
+    (text, placeholders)
+}
+
+/// Splits a line into spans so that the parts matching the terms are highlighted
+fn highlight(text: &str, terms: &[String], style: Style) -> Line<'static> {
+    let mut spans = vec![];
+    let mut last = 0;
+    for range in search::matches(text, terms) {
+        spans.push(Span::styled(text[last..range.start].to_string(), style));
+        spans.push(Span::styled(
+            text[range.clone()].to_string(),
+            style.patch(HIGHLIGHT),
+        ));
+        last = range.end;
+    }
+    spans.push(Span::styled(text[last..].to_string(), style));
+
+    Line::from(spans)
+}
 
+/// The article in a bordered paragraph, `text` is its converted text
+fn article_paragraph(
+    article: &Article,
+    text: &str,
+    scroll: u16,
+    terms: &[String],
+) -> Paragraph<'static> {
+    let text: Text = text
+        .lines()
+        .map(|line| highlight(line, terms, Style::default()))
+        .collect::<Vec<Line>>()
+        .into();
+
+    Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(Span::styled(
+            article.title.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )))
+        .alignment(Alignment::Left)
+        .wrap(Wrap { trim: true })
+        .scroll((scroll, 0))
+}
+
+/// Episode and enclosures of a podcast above the article, returns the area left for the article
+fn enclosures_ui(f: &mut Frame, article: &Article, selected: Option<usize>, area: Rect) -> Rect {
+    if article.enclosures.is_empty() && article.episode.is_none() {
+        return area;
+    }
+
+    let mut lines: Vec<Line> = article
+        .episode
+        .iter()
+        .map(|episode| Line::from(format!("Episode {}", episode)))
+        .collect();
+    for (index, found) in article.enclosures.iter().enumerate() {
+        let style = if selected == Some(index) {
+            Style::default()
+                .bg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::styled(
+            format!("{}. {} ({})", index + 1, found.url, found.describe()),
+            style,
+        ));
+    }
+
+    let [header, rest] = Layout::vertical([
+        Constraint::Length(lines.len() as u16 + 2),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let title = if article.enclosures.is_empty() {
+        "Podcast"
+    } else {
+        "Enclosures (e: next, p: play)"
+    };
+    f.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        header,
+    );
+
+    rest
+}
+
+fn read_ui(f: &mut Frame, app: &App, article: &Article, area: Rect) {
     let block = Block::default();
     f.render_widget(block, area);
 
@@ -324,25 +1654,231 @@ fn read_ui<B: Backend>(f: &mut Frame, app: &App, article: &Article) {
         .constraints(Constraint::from_fills([1]))
         .split(area);
 
-    let raw_html = article.content.clone();
-    let parsed_to_markdown = html2md::parse_html(&raw_html);
-    let markdown_to_terminal = termimad::inline(&parsed_to_markdown).to_string();
-    let ansi_to_tui = markdown_to_terminal.into_text().unwrap();
+    let content = enclosures_ui(f, article, Some(app.enclosure), chunks[0]);
+    let rows = app.images.rows();
+    let paragraph = app.converted(article, rows, |converted| {
+        article_paragraph(article, &converted.text, app.scroll, &app.find)
+    });
+    f.render_widget(paragraph, content);
+    if rows > 0 {
+        images_ui(f, app, article, content.inner(Margin::new(1, 1)));
+    }
+}
 
-    let create_block = |title| {
-        Block::default().borders(Borders::ALL).title(Span::styled(
-            title,
-            Style::default().add_modifier(Modifier::BOLD),
-        ))
+/// Draws the images of the article in the room left under their placeholder, the ones only
+/// partly on screen are left out
+fn images_ui(f: &mut Frame, app: &App, article: &Article, area: Rect) {
+    let rows = app.images.rows();
+    let placeholders: Vec<(usize, Image)> = app.converted(article, rows, |converted| {
+        // rows taken by the wrapped text down to the placeholder
+        let above = converted.above(area.width).to_vec();
+        above
+            .into_iter()
+            .zip(
+                converted
+                    .placeholders
+                    .iter()
+                    .map(|(_, image)| image.clone()),
+            )
+            .collect()
+    });
+
+    for (above, image) in placeholders {
+        let Some(top) = above.checked_sub(app.scroll as usize) else {
+            continue;
+        };
+        if top + rows as usize > area.height as usize {
+            continue;
+        }
+
+        let slot = Rect::new(area.x, area.y + top as u16, area.width, rows);
+        app.images.render(&image.src, slot, f.buffer_mut());
+    }
+}
+
+/// Bottom line showing the progress of a refresh, or the last message
+fn status_ui(f: &mut Frame, app: &App, area: Rect) {
+    match &app.progress {
+        Some(progress) => {
+            let done = progress.total - progress.pending.len();
+            let gauge = LineGauge::default()
+                .filled_style(Style::default().fg(Color::LightGreen))
+                .label(format!("Refreshing {}/{}", done, progress.total))
+                .ratio(done as f64 / progress.total as f64);
+            f.render_widget(gauge, area);
+        }
+        None => f.render_widget(Paragraph::new(app.status.clone()), area),
+    }
+}
+
+/// Highlights the characters of a finder candidate matching the typed pattern
+fn fuzzy_line(pattern: &str, candidate: &str) -> Line<'static> {
+    let positions = fuzzy_match(pattern, candidate)
+        .map(|m| m.positions)
+        .unwrap_or_default();
+
+    candidate
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if positions.contains(&i) {
+                Span::styled(c.to_string(), HIGHLIGHT)
+            } else {
+                Span::raw(c.to_string())
+            }
+        })
+        .collect::<Vec<Span>>()
+        .into()
+}
+
+fn finder_ui(f: &mut Frame, finder: &mut Finder) {
+    let area = f.area();
+    let area = Rect::new(
+        area.x + area.width / 5,
+        area.y + area.height / 5,
+        area.width * 3 / 5,
+        area.height * 3 / 5,
+    );
+    f.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Fill(1)])
+        .split(area);
+
+    let title = match finder.scope {
+        FinderScope::Sidebar => "Find website",
+        FinderScope::Articles => "Find article",
+        FinderScope::Everywhere => "Find article in every website",
     };
+    f.render_widget(
+        Paragraph::new(finder.input.clone())
+            .style(Style::default().fg(Color::Green))
+            .block(Block::default().borders(Borders::ALL).title(title)),
+        chunks[0],
+    );
+    f.set_cursor_position(Position::new(
+        chunks[0].x + finder.input.width() as u16 + 1,
+        chunks[0].y + 1,
+    ));
 
-    let paragraph = Paragraph::new(ansi_to_tui.to_string())
-        .block(create_block(article.title.clone()))
-        .alignment(Alignment::Left)
-        .wrap(Wrap { trim: true })
-        .scroll((app.scroll, 0));
+    let matches: Vec<ListItem> = finder
+        .matches
+        .items
+        .iter()
+        .map(|&candidate| ListItem::new(fuzzy_line(&finder.input, &finder.candidates[candidate].0)))
+        .collect();
 
-    f.render_widget(paragraph, chunks[0]);
+    let list = List::new(matches)
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "{}/{}",
+            finder.matches.items.len(),
+            finder.candidates.len()
+        )))
+        .highlight_style(
+            Style::default()
+                .bg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    f.render_stateful_widget(list, chunks[1], &mut finder.matches.state);
+}
+
+fn prompt_ui(f: &mut Frame, prompt: &Prompt) {
+    let area = f.area();
+    let area = Rect::new(
+        area.x,
+        area.bottom().saturating_sub(3),
+        area.width,
+        area.height.min(3),
+    );
+
+    let title = match prompt.kind {
+        PromptKind::Search => "Search all articles",
+        PromptKind::Find => "Find in article",
+        PromptKind::Export => "Export the starred articles to (.json, .md or .atom)",
+        PromptKind::Epub => "Pack the articles of the list into an EPUB at",
+    };
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(prompt.input.clone())
+            .style(Style::default().fg(Color::Green))
+            .block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+    // For x, put cursor past the end of the input text
+    // For y, move one line down, from the border to the input line
+    f.set_cursor_position(Position::new(
+        area.x + prompt.input.width() as u16 + 1,
+        area.y + 1,
+    ));
+}
+
+/// Asks whether a change of the subscriptions should be made
+fn confirm_ui(f: &mut Frame, change: &Change) {
+    let area = f.area();
+    let area = Rect::new(
+        area.x + area.width / 5,
+        area.y + area.height / 2 - area.height.min(5) / 2,
+        area.width * 3 / 5,
+        area.height.min(5),
+    );
+
+    let (title, question) = match change {
+        Change::Move { from, to } => (
+            "Feed moved",
+            format!(
+                "{} moved permanently to {}, update the subscription?",
+                from, to
+            ),
+        ),
+        Change::Remove(url) => (
+            "Feed gone",
+            format!("{} is gone for good, remove the subscription?", url),
+        ),
+    };
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(format!("{}\n(y)es / (n)o", question))
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
+
+/// Downloads of this run with their progress, one per line
+fn downloads_ui(f: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(Span::styled(
+        format!("Downloads ({} in progress)", app.downloader.active()),
+        Style::default().add_modifier(Modifier::BOLD),
+    ));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    if app.downloader.downloads.is_empty() {
+        f.render_widget(
+            Paragraph::new("Nothing downloaded yet, d downloads the enclosure of an article"),
+            inner,
+        );
+        return;
+    }
+
+    let rows = Layout::vertical(vec![Constraint::Length(1); inner.height as usize]).split(inner);
+    for (download, row) in app.downloader.downloads.iter().zip(rows.iter()) {
+        let color = match download.status {
+            Status::Done => Color::LightGreen,
+            Status::Failed(_) => Color::Red,
+            Status::Queued | Status::Running => Color::LightBlue,
+        };
+        let gauge = LineGauge::default()
+            .filled_style(Style::default().fg(color))
+            .label(download.label())
+            .ratio(download.ratio());
+        f.render_widget(gauge, *row);
+    }
 }
 
 fn help_ui(f: &mut Frame) {
@@ -364,7 +1900,7 @@ fn help_ui(f: &mut Frame) {
         ))
     };
 
-    let paragraph = Paragraph::new("ENTER - choose website/article\nARROW KEYS - Navigate the UI\nESC - Go back a panel\nQ - Go back a panel\n\n\nNOTE: When the application does not have a website selected hitting ESC or Q will close the application".to_string())
+    let paragraph = Paragraph::new("ENTER - choose website/article\nARROW KEYS - Navigate the UI\nM - Mark the selected article read/unread\n/ - Search all articles, in an article find text\nN / SHIFT+N - Jump to the next/previous match in an article\nF - Filter the websites or articles list as you type\nSHIFT+F - Find an article by title in every website\nSPACE - Collapse or expand the selected folder\nR - Refresh the selected website or folder\nSHIFT+R - Refresh every website\nE - Select the next enclosure of an article\nP - Play the selected enclosure\nD - Download the enclosure of an article\nSHIFT+D - Show the downloads\nS - Star or unstar the selected article\nX - Export the starred articles\nW - Save the selected article as Markdown\nSHIFT+W - Save every article of the list as Markdown\nB - Pack the articles of the list into an EPUB\nESC - Go back a panel\nQ - Go back a panel\n\n\nNOTE: When the application does not have a website selected hitting ESC or Q will close the application".to_string())
         .block(create_block("Key Shortcuts"))
         .alignment(Alignment::Left)
         .wrap(Wrap { trim: false });
@@ -372,23 +1908,90 @@ fn help_ui(f: &mut Frame) {
     f.render_widget(paragraph, chunks[0]);
 }
 
-fn ui(f: &mut Frame, app: &mut App) {
-    // Create two chunks with divided horizontal screen space (20/80)
-    // for a sidebar displaying websites and a list of articles
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(20), Constraint::Fill(1)])
-        .split(f.area());
+/// Splits the screen into the websites, articles and (optionally) preview panes
+fn panes(layout: &config::Layout, area: Rect) -> Vec<Rect> {
+    let direction = match layout.orientation {
+        Orientation::Horizontal => Direction::Horizontal,
+        Orientation::Stacked => Direction::Vertical,
+        Orientation::Auto if area.width < layout.stack_below => Direction::Vertical,
+        Orientation::Auto => Direction::Horizontal,
+    };
+
+    let [websites, articles, preview] = layout.panes;
+    let constraints = if layout.preview {
+        vec![
+            Constraint::Fill(websites),
+            Constraint::Fill(articles),
+            Constraint::Fill(preview),
+        ]
+    } else {
+        // without the preview the article list takes its share too
+        vec![
+            Constraint::Fill(websites),
+            Constraint::Fill(articles.saturating_add(preview)),
+        ]
+    };
+
+    Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .split(area)
+        .to_vec()
+}
+
+fn ui(f: &mut Frame, app: &mut App, area: Rect) {
+    // By default two chunks divide the screen (20/80) into a sidebar displaying websites
+    // and a list of articles, the three-pane layout adds a preview of the selected article
+    let chunks = panes(&app.layout, area);
 
     // Iterate through all elements in the `items` app and append some debug text to it.
     let sites: Vec<ListItem> = app
-        .websites
+        .sidebar
         .items
         .iter()
-        .map(|site| {
+        .map(|entry| {
+            let name = app.entry_name(entry);
+            // folders show the unread articles of all their websites
+            let label = match entry {
+                Entry::Folder(folder) => {
+                    let (folder_name, websites) = &app.folders[*folder];
+                    let arrow = if app.state.collapsed.contains(folder_name) {
+                        "▸"
+                    } else {
+                        "▾"
+                    };
+                    format!("{} {} ({})", arrow, name, app.unread(websites))
+                }
+                Entry::Website { index, folder } => {
+                    let indent = if folder.is_some() { "  " } else { "" };
+                    let label = match app.unread(&[*index]) {
+                        0 => format!("{}{}", indent, name),
+                        unread => format!("{}{} ({})", indent, name, unread),
+                    };
+                    match app.health(&app.websites[*index].url) {
+                        Some(health) => format!("{} [{}]", label, health),
+                        None => label,
+                    }
+                }
+                Entry::Starred => format!("{} ({})", name, app.starred.articles.len()),
+                _ => name,
+            };
+
+            // websites that stopped answering stand out in red
+            let fg = match entry {
+                Entry::Website { index, .. }
+                    if app.state.paused.contains(&app.websites[*index].url)
+                        || app
+                            .state
+                            .is_dead(&app.websites[*index].url, app.fetch.dead_after_days) =>
+                {
+                    Color::Red
+                }
+                _ => Color::Black,
+            };
+
             // adds the website name
-            ListItem::new(site.name.clone())
-                .style(Style::default().fg(Color::Black).bg(Color::White))
+            ListItem::new(label).style(Style::default().fg(fg).bg(Color::White))
         })
         .collect();
 
@@ -403,25 +2006,74 @@ fn ui(f: &mut Frame, app: &mut App) {
         .highlight_symbol(">> ");
 
     // We can now render the item list
-    f.render_stateful_widget(items, chunks[0], &mut app.websites.state);
+    f.render_stateful_widget(items, chunks[0], &mut app.sidebar.state);
+
+    // virtual feeds mix websites so every article says where it comes from
+    let show_feed = matches!(
+        app.sidebar.selected(),
+        Some(
+            Entry::Timeline(_)
+                | Entry::Starred
+                | Entry::Search
+                | Entry::Query(_)
+                | Entry::Folder(_)
+        )
+    );
+    let terms = app.search_terms();
 
     let entries: Vec<ListItem> = {
         app.articles
             .items
             .iter()
             .map(|article| {
-                ListItem::new(format!(
-                    "{}\n{}",
-                    article.title.clone(),
+                let date = if show_feed {
+                    format!("{} | {}", article.feed, article.updated_at)
+                } else {
                     article.updated_at.clone()
-                ))
-                .style(Style::default().fg(Color::Black).bg(Color::White))
+                };
+                let style = Style::default().fg(Color::Black).bg(article
+                    .color
+                    .as_ref()
+                    .and_then(|color| color.parse().ok())
+                    .unwrap_or(Color::White));
+                // unread articles stand out in bold
+                let style = if app.state.is_read(article) {
+                    style
+                } else {
+                    style.add_modifier(Modifier::BOLD)
+                };
+
+                let mut title = highlight(&article.title, &terms, style);
+                // articles that arrived with a refresh are marked until they are opened
+                if app.new.contains(&article.id) {
+                    title
+                        .spans
+                        .insert(0, Span::styled("NEW ", style.fg(Color::Blue)));
+                }
+                if app.starred.contains(article) {
+                    title
+                        .spans
+                        .insert(0, Span::styled("★ ", style.fg(Color::Yellow)));
+                }
+
+                ListItem::new(vec![title, Line::styled(date, style)]).style(style)
             })
             .collect()
     };
 
+    // feeds that had to be repaired say how
+    let title = match app.sidebar.selected() {
+        Some(Entry::Website { index, .. }) if !app.websites[*index].repairs.is_empty() => {
+            format!(
+                "Articles (repaired: {})",
+                app.websites[*index].repairs.join(", ")
+            )
+        }
+        _ => "Articles".to_string(),
+    };
+
     let entries_list = List::new(entries)
-        .block(Block::default().borders(Borders::ALL).title("Articles"))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .highlight_style(
             Style::default()
                 .bg(Color::LightGreen)
@@ -431,4 +2083,20 @@ fn ui(f: &mut Frame, app: &mut App) {
         .highlight_spacing(HighlightSpacing::Always);
 
     f.render_stateful_widget(entries_list, chunks[1], &mut app.articles.state);
+
+    if let Some(area) = chunks.get(2) {
+        match app.articles.selected() {
+            Some(article) => {
+                let content = enclosures_ui(f, article, None, *area);
+                let paragraph = app.converted(article, 0, |converted| {
+                    article_paragraph(article, &converted.text, 0, &terms)
+                });
+                f.render_widget(paragraph, content)
+            }
+            None => f.render_widget(
+                Block::default().borders(Borders::ALL).title("Preview"),
+                *area,
+            ),
+        }
+    }
 }
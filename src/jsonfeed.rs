@@ -0,0 +1,162 @@
+//! The JSON Feed format, versions 1.0 and 1.1 (https://www.jsonfeed.org/version/1.1/).
+//! Only the fields trss shows are read, unknown fields and extensions are ignored.
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Prefix of the `version` of every JSON Feed
+const VERSION: &str = "https://jsonfeed.org/version/";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct JsonFeed {
+    pub version: String,
+    pub title: String,
+    pub home_page_url: Option<String>,
+    /// 1.1
+    pub authors: Vec<Author>,
+    /// 1.1
+    pub language: Option<String>,
+    /// 1.0, replaced by `authors` in 1.1
+    pub author: Option<Author>,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Author {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Item {
+    /// A string, but some feeds use numbers
+    pub id: Value,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub content_html: Option<String>,
+    pub content_text: Option<String>,
+    pub summary: Option<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    pub authors: Vec<Author>,
+    pub author: Option<Author>,
+    pub tags: Vec<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Attachment {
+    pub url: String,
+    pub mime_type: String,
+    pub size_in_bytes: Option<u64>,
+    pub duration_in_seconds: Option<f64>,
+}
+
+impl JsonFeed {
+    /// Parses a JSON Feed, other JSON documents are refused
+    pub fn parse(content: &str) -> Result<JsonFeed, String> {
+        let feed: JsonFeed = serde_json::from_str(content).map_err(|e| e.to_string())?;
+        if !feed.version.starts_with(VERSION) {
+            return Err(format!("\"{}\" is not a JSON Feed version", feed.version));
+        }
+        Ok(feed)
+    }
+
+    /// Names of the authors of the feed
+    pub fn author_names(&self) -> String {
+        names(&self.authors, &self.author)
+    }
+}
+
+impl Item {
+    /// The id, else the URL, else a hash of the title, date and content so items without either
+    /// still tell apart
+    pub fn id(&self) -> String {
+        match &self.id {
+            Value::String(id) if !id.is_empty() => id.clone(),
+            Value::String(_) | Value::Null => match self.url.as_deref() {
+                Some(url) if !url.is_empty() => url.to_string(),
+                _ => self.hash(),
+            },
+            id => id.to_string(),
+        }
+    }
+
+    fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            &self.title,
+            &self.date_published,
+            &self.content_html,
+            &self.content_text,
+        ] {
+            hasher.update(part.as_deref().unwrap_or_default());
+            // keeps ("ab", "c") apart from ("a", "bc")
+            hasher.update([0]);
+        }
+        format!("sha256:{:x}", hasher.finalize())
+    }
+
+    pub fn author_names(&self) -> String {
+        names(&self.authors, &self.author)
+    }
+
+    /// The HTML content, or the text content escaped to HTML
+    pub fn content(&self) -> String {
+        match (&self.content_html, &self.content_text) {
+            (Some(html), _) => html.clone(),
+            (None, Some(text)) => escape(text).replace('\n', "<br>"),
+            (None, None) => "N/A".to_string(),
+        }
+    }
+}
+
+fn names(authors: &[Author], author: &Option<Author>) -> String {
+    authors
+        .iter()
+        .chain(author.iter())
+        .filter_map(|author| author.name.clone())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(items: &str) -> Vec<String> {
+        let content = format!(
+            r#"{{"version": "https://jsonfeed.org/version/1.1", "title": "T", "items": {}}}"#,
+            items
+        );
+        let feed = JsonFeed::parse(&content).unwrap();
+        feed.items.iter().map(Item::id).collect()
+    }
+
+    #[test]
+    fn items_without_id_or_url_get_distinct_ids() {
+        let ids = ids(r#"[{"id": null, "title": "One", "content_text": "a"},
+                {"title": "Two", "content_text": "a"},
+                {"id": "", "title": "Two", "content_text": "a"}]"#);
+        assert_ne!(ids[0], ids[1]);
+        assert!(ids[0].starts_with("sha256:"));
+        // the same item keeps its id
+        assert_eq!(ids[1], ids[2]);
+    }
+
+    #[test]
+    fn ids_fall_back_to_the_url() {
+        let ids = ids(r#"[{"id": 7, "url": "a"}, {"url": "https://example.com/1"}]"#);
+        assert_eq!(ids, ["7", "https://example.com/1"]);
+    }
+}
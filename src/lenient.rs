@@ -0,0 +1,195 @@
+//! Repairs for the mistakes feeds commonly make that keep strict XML parsers from reading them.
+//! The repairs are only tried when a feed cannot be parsed as it is. A byte order mark is already
+//! gone, `charset::decode` takes it out.
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// A well-formed XML declaration
+const DECLARATION: &str = r#"^<\?xml\s+version\s*=\s*["']1\.[01]["'](\s+encoding\s*=\s*["'][A-Za-z][\w.-]*["'])?(\s+standalone\s*=\s*["'](yes|no)["'])?\s*\?>"#;
+
+/// HTML entities often found in feeds, XML only knows `&amp;`, `&lt;`, `&gt;`, `&quot;` and
+/// `&apos;`
+const ENTITIES: [(&str, u32); 30] = [
+    ("nbsp", 160),
+    ("iexcl", 161),
+    ("cent", 162),
+    ("pound", 163),
+    ("copy", 169),
+    ("laquo", 171),
+    ("reg", 174),
+    ("deg", 176),
+    ("middot", 183),
+    ("raquo", 187),
+    ("iquest", 191),
+    ("agrave", 224),
+    ("aacute", 225),
+    ("auml", 228),
+    ("ccedil", 231),
+    ("egrave", 232),
+    ("eacute", 233),
+    ("ouml", 246),
+    ("uuml", 252),
+    ("szlig", 223),
+    ("times", 215),
+    ("ndash", 8211),
+    ("mdash", 8212),
+    ("lsquo", 8216),
+    ("rsquo", 8217),
+    ("ldquo", 8220),
+    ("rdquo", 8221),
+    ("bull", 8226),
+    ("hellip", 8230),
+    ("euro", 8364),
+];
+
+const XML_ENTITIES: [&str; 5] = ["amp", "lt", "gt", "quot", "apos"];
+
+static ANY_DECLARATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<\?xml\b[^>]*>").unwrap());
+static VALID_DECLARATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(DECLARATION).unwrap());
+static ENTITY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(#[0-9]+;|#[xX][0-9a-fA-F]+;|([A-Za-z][A-Za-z0-9]*);)?").unwrap()
+});
+
+/// Repairs the feed, returns the repaired text and a description of every repair that changed
+/// something
+pub(crate) fn repair(content: &str) -> (String, Vec<&'static str>) {
+    let mut repairs = vec![];
+
+    let mut text = content;
+    if text.starts_with(char::is_whitespace) {
+        repairs.push("removed whitespace before the XML");
+        text = text.trim_start();
+    }
+
+    let mut text = text.to_string();
+    if let Some(repaired) = declaration(&text) {
+        repairs.push("removed a malformed XML declaration");
+        text = repaired;
+    }
+
+    let (repaired, ampersands, entities) = escape(&text);
+    if ampersands {
+        repairs.push("escaped bare ampersands");
+    }
+    if entities {
+        repairs.push("replaced HTML entities");
+    }
+
+    (repaired, repairs)
+}
+
+/// Drops an XML declaration that is malformed or not at the very start, where it must be. Without
+/// a declaration the parser reads XML 1.0.
+fn declaration(text: &str) -> Option<String> {
+    let found = ANY_DECLARATION.find(text)?;
+    if found.start() == 0 && VALID_DECLARATION.is_match(found.as_str()) {
+        return None;
+    }
+
+    let mut repaired = text.to_string();
+    repaired.replace_range(found.range(), "");
+    Some(repaired)
+}
+
+/// Escapes the ampersands that don't start an entity and turns HTML entities into character
+/// references, leaving CDATA sections alone. Returns whether ampersands and entities were fixed.
+fn escape(text: &str) -> (String, bool, bool) {
+    let (mut ampersands, mut entities) = (false, false);
+    let mut repaired = String::with_capacity(text.len());
+
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (outside, cdata, next) = match rest.find("<![CDATA[") {
+            Some(start) => {
+                let end = rest[start..]
+                    .find("]]>")
+                    .map_or(rest.len(), |end| start + end + 3);
+                (&rest[..start], &rest[start..end], &rest[end..])
+            }
+            None => (rest, "", ""),
+        };
+
+        let escaped = ENTITY.replace_all(outside, |captures: &Captures| {
+            match (captures.get(1), captures.get(2)) {
+                (None, _) => {
+                    ampersands = true;
+                    "&amp;".to_string()
+                }
+                (Some(_), Some(name)) if !XML_ENTITIES.contains(&name.as_str()) => {
+                    entities = true;
+                    match ENTITIES.iter().find(|(entity, _)| *entity == name.as_str()) {
+                        Some((_, code)) => format!("&#{};", code),
+                        // unknown entities are kept as text
+                        None => format!("&amp;{};", name.as_str()),
+                    }
+                }
+                _ => captures[0].to_string(),
+            }
+        });
+        repaired.push_str(&escaped);
+        repaired.push_str(cdata);
+        rest = next;
+    }
+
+    (repaired, ampersands, entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEM: &str = "<rss version=\"2.0\"><channel><title>Feed</title>\
+        <link>https://example.com/</link><description>d</description><item>\
+        <title>Fish &amp; chips</title></item></channel></rss>";
+
+    #[test]
+    fn well_formed_feeds_are_left_alone() {
+        let feed = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", ITEM);
+        assert_eq!(repair(&feed), (feed, vec![]));
+    }
+
+    #[test]
+    fn whitespace_and_misplaced_declarations_are_removed() {
+        let (repaired, repairs) = repair(&format!("\n  <?xml version=\"1.0\"?>{}", ITEM));
+        assert_eq!(repaired, format!("<?xml version=\"1.0\"?>{}", ITEM));
+        assert_eq!(repairs, ["removed whitespace before the XML"]);
+
+        let (repaired, repairs) = repair(&format!("<?xml version=1.0 ?>{}", ITEM));
+        assert_eq!(repaired, ITEM);
+        assert_eq!(repairs, ["removed a malformed XML declaration"]);
+
+        let (repaired, _) = repair(&format!("<!-- x --><?xml version=\"1.0\"?>{}", ITEM));
+        assert_eq!(repaired, format!("<!-- x -->{}", ITEM));
+    }
+
+    #[test]
+    fn ampersands_and_entities_are_escaped_outside_cdata() {
+        let (repaired, repairs) = repair(
+            "<a>Q&A &copy; &eacute;t&eacute; &unknown; &amp; &#233; &#xE9; &lt;\
+             <![CDATA[a & b &nbsp;]]> &nbsp;</a>",
+        );
+        assert_eq!(
+            repaired,
+            "<a>Q&amp;A &#169; &#233;t&#233; &amp;unknown; &amp; &#233; &#xE9; &lt;\
+             <![CDATA[a & b &nbsp;]]> &#160;</a>"
+        );
+        assert_eq!(
+            repairs,
+            ["escaped bare ampersands", "replaced HTML entities"]
+        );
+    }
+
+    #[test]
+    fn repaired_feeds_parse() {
+        let broken = "  <?xml version='1.0' encoding='utf-8' ?>\
+            <rss version=\"2.0\"><channel><title>Caf&eacute; & co</title>\
+            <link>https://example.com/?a=1&b=2</link><description>d</description>\
+            </channel></rss>";
+        assert!(broken.parse::<rss::Channel>().is_err());
+        let (repaired, _) = repair(broken);
+        let channel: rss::Channel = repaired.parse().unwrap();
+        assert_eq!(channel.title(), "Café & co");
+        assert_eq!(channel.link(), "https://example.com/?a=1&b=2");
+    }
+}
@@ -0,0 +1,86 @@
+//! Makes the links and images of articles absolute when they are ingested, relative ones would
+//! point nowhere once the article is out of its feed.
+use regex::{Captures, Regex};
+use reqwest::Url;
+
+/// Resolves a reference against a base, keeps the base when there is no reference or it is
+/// invalid. Used for `xml:base` as well as for links.
+pub(crate) fn join(base: &Url, reference: Option<&str>) -> Url {
+    reference
+        .map(str::trim)
+        .filter(|reference| !reference.is_empty())
+        .and_then(|reference| base.join(reference).ok())
+        .unwrap_or_else(|| base.clone())
+}
+
+/// Rewrites the `href`, `src` and `poster` attributes of an HTML fragment against a base
+pub(crate) fn resolve(html: &str, base: &Url) -> String {
+    let attribute =
+        Regex::new(r#"(?i)(\s(?:href|src|poster)\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).unwrap();
+
+    attribute
+        .replace_all(html, |captures: &Captures| {
+            let (quote, value) = match captures.get(2) {
+                Some(value) => ('"', value.as_str()),
+                None => ('\'', &captures[3]),
+            };
+            // fragments point inside the article itself
+            if value.trim().is_empty() || value.starts_with('#') {
+                return captures[0].to_string();
+            }
+
+            match base.join(value.trim()) {
+                Ok(url) => format!("{}{}{}{}", &captures[1], quote, url, quote),
+                Err(_) => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://example.com/blog/post/").unwrap()
+    }
+
+    #[test]
+    fn join_keeps_the_base_without_a_usable_reference() {
+        for reference in [None, Some(""), Some("  "), Some("http://[::1")] {
+            assert_eq!(join(&base(), reference), base());
+        }
+        assert_eq!(
+            join(&base(), Some(" ../other/ ")).as_str(),
+            "https://example.com/blog/other/"
+        );
+        assert_eq!(
+            join(&base(), Some("https://cdn.example.org/a.png")).as_str(),
+            "https://cdn.example.org/a.png"
+        );
+    }
+
+    #[test]
+    fn relative_attributes_become_absolute() {
+        assert_eq!(
+            resolve(
+                "<a href=\"next\">n</a><img src='/images/a.png'><video poster=\"../p.jpg\">",
+                &base()
+            ),
+            "<a href=\"https://example.com/blog/post/next\">n</a>\
+             <img src='https://example.com/images/a.png'>\
+             <video poster=\"https://example.com/blog/p.jpg\">"
+        );
+    }
+
+    #[test]
+    fn absolute_and_fragment_attributes_are_left_alone() {
+        let html = "<a href=\"https://other.org/x\">x</a><a href=\"#notes\">notes</a>\
+                    <img src=\"\"><a title=\"next\">t</a>";
+        assert_eq!(resolve(html, &base()), html);
+        assert_eq!(
+            resolve("<A HREF = \"//cdn.example.org/a\">", &base()),
+            "<A HREF = \"https://cdn.example.org/a\">"
+        );
+    }
+}
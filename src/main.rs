@@ -1,11 +1,32 @@
+mod charset;
+mod cli;
 mod config;
+mod digest;
+mod discover;
+mod download;
+mod enclosure;
+mod epub;
 mod error;
+mod export;
+mod filter;
+mod fuzzy;
+mod images;
 mod input;
 mod interface;
+mod jsonfeed;
+mod lenient;
+mod links;
+mod query;
+mod refresh;
 mod rss;
+mod search;
+mod serve;
+mod starred;
+mod state;
+mod timeline;
 
 use crate::interface::{run_app, App};
-use std::{error::Error, io, time::Duration};
+use std::{env, error::Error, io, process, time::Duration};
 
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
@@ -14,6 +35,20 @@ use crossterm::{
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 fn main() -> Result<(), Box<dyn Error>> {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    if let Some(command) = arguments.first() {
+        if let Err(error) = cli::run(command, &arguments[1..]) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // create app before the terminal is set up, so a bad configuration is reported in a usable
+    // shell
+    let config = config::load()?;
+    let app = App::new(config)?;
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -21,10 +56,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // create app and run it
+    // run it
     let tick_rate = Duration::from_millis(250);
-    let config = config::load()?;
-    let app = App::new(config);
     let res = run_app(&mut terminal, app, tick_rate);
 
     // restore terminal
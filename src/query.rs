@@ -0,0 +1,430 @@
+//! The query language of the virtual feeds, for example
+//! `tag:rust and unread and title~"release"` or `age<7d and author:"Charity"`.
+//!
+//! * `field:value` matches when the field contains the value, ignoring case
+//! * `field~pattern` matches the field against a regular expression
+//! * fields are `title`, `author`, `feed`, `content` and `tag` (a category of the article or a
+//!   folder of its website)
+//! * `read` and `unread` match the read state
+//! * `age<7d` and `age>12h` compare the age of the article, in hours, days or weeks
+//! * predicates combine with `and`, `or`, `not` and parentheses, `and` binds tighter than `or`
+use crate::{
+    rss::{Article, Website},
+    state::State,
+};
+
+use jiff::{SignedDuration, Timestamp};
+use regex::{Regex, RegexBuilder};
+use std::{cmp::Reverse, iter::Peekable, str::Chars};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Colon,
+    Tilde,
+    Less,
+    Greater,
+    Open,
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Field {
+    Title,
+    Author,
+    Feed,
+    Content,
+    Tag,
+}
+
+#[derive(Debug)]
+pub(crate) enum Matcher {
+    Contains(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+pub(crate) enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Read,
+    Unread,
+    Field(Field, Matcher),
+    /// Published less than the duration ago
+    NewerThan(SignedDuration),
+    /// Published more than the duration ago
+    OlderThan(SignedDuration),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars: Peekable<Chars> = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            ':' => tokens.push(Token::Colon),
+            '~' => tokens.push(Token::Tilde),
+            '<' => tokens.push(Token::Less),
+            '>' => tokens.push(Token::Greater),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => quoted.extend(chars.next()),
+                        Some(c) => quoted.push(c),
+                        None => return Err(format!("missing closing quote after \"{quoted}")),
+                    }
+                }
+                tokens.push(Token::Quoted(quoted));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || ":~<>()\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parses durations like `12h`, `7d` or `2w`, negative ones are refused
+pub(crate) fn duration(text: &str) -> Result<SignedDuration, String> {
+    let invalid = || format!("invalid duration \"{text}\", expected something like 7d");
+    let unit = text.chars().last().ok_or_else(invalid)?;
+    let count: u32 = text[..text.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+
+    let hours = match unit {
+        'h' => 1,
+        'd' => 24,
+        'w' => 24 * 7,
+        _ => {
+            return Err(format!(
+                "invalid duration unit in \"{text}\", use h, d or w"
+            ))
+        }
+    };
+
+    Ok(SignedDuration::from_hours(i64::from(count) * hours))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Query, String> {
+        let mut query = self.and()?;
+        while self.keyword("or") {
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, String> {
+        let mut query = self.not()?;
+        while self.keyword("and") {
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+        Ok(query)
+    }
+
+    fn not(&mut self) -> Result<Query, String> {
+        if self.keyword("not") {
+            return Ok(Query::Not(Box::new(self.not()?)));
+        }
+
+        match self.next() {
+            Some(Token::Open) => {
+                let query = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err("missing closing parenthesis".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => self.predicate(&word.to_lowercase()),
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Err("unexpected end of the query".to_string()),
+        }
+    }
+
+    fn predicate(&mut self, word: &str) -> Result<Query, String> {
+        let field = match word {
+            "read" => return Ok(Query::Read),
+            "unread" => return Ok(Query::Unread),
+            "age" => {
+                let newer = match self.next() {
+                    Some(Token::Less) => true,
+                    Some(Token::Greater) => false,
+                    _ => return Err("age must be followed by < or >".to_string()),
+                };
+                let age = match self.next() {
+                    Some(Token::Word(age)) => duration(&age)?,
+                    _ => return Err("age is missing a duration".to_string()),
+                };
+                return Ok(if newer {
+                    Query::NewerThan(age)
+                } else {
+                    Query::OlderThan(age)
+                });
+            }
+            "title" => Field::Title,
+            "author" => Field::Author,
+            "feed" => Field::Feed,
+            "content" => Field::Content,
+            "tag" => Field::Tag,
+            word => return Err(format!("unknown keyword \"{word}\"")),
+        };
+
+        let regex = match self.next() {
+            Some(Token::Colon) => false,
+            Some(Token::Tilde) => true,
+            _ => return Err(format!("{word} must be followed by : or ~")),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => value,
+            _ => return Err(format!("{word} is missing a value")),
+        };
+
+        let matcher = if regex {
+            Matcher::Regex(
+                RegexBuilder::new(&value)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| e.to_string())?,
+            )
+        } else {
+            Matcher::Contains(value.to_lowercase())
+        };
+
+        Ok(Query::Field(field, matcher))
+    }
+}
+
+impl Matcher {
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Contains(value) => text.to_lowercase().contains(value),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, String> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            position: 0,
+        };
+
+        let parsed = parser.or()?;
+        match parser.next() {
+            None => Ok(parsed),
+            Some(token) => Err(format!("unexpected {token:?}")),
+        }
+    }
+
+    pub fn matches(
+        &self,
+        website: &Website,
+        article: &Article,
+        state: &State,
+        now: Timestamp,
+    ) -> bool {
+        match self {
+            Query::And(left, right) => {
+                left.matches(website, article, state, now)
+                    && right.matches(website, article, state, now)
+            }
+            Query::Or(left, right) => {
+                left.matches(website, article, state, now)
+                    || right.matches(website, article, state, now)
+            }
+            Query::Not(query) => !query.matches(website, article, state, now),
+            Query::Read => state.is_read(article),
+            Query::Unread => !state.is_read(article),
+            Query::Field(Field::Title, matcher) => matcher.is_match(&article.title),
+            Query::Field(Field::Author, matcher) => matcher.is_match(&article.author),
+            Query::Field(Field::Feed, matcher) => matcher.is_match(&article.feed),
+            Query::Field(Field::Content, matcher) => {
+                matcher.is_match(&article.content)
+                    || article
+                        .subtitle
+                        .as_deref()
+                        .is_some_and(|subtitle| matcher.is_match(subtitle))
+            }
+            Query::Field(Field::Tag, matcher) => article
+                .categories
+                .iter()
+                .chain(website.tags.iter())
+                .any(|tag| matcher.is_match(tag)),
+            Query::NewerThan(age) => article
+                .published()
+                .is_some_and(|published| now.duration_since(published) < *age),
+            Query::OlderThan(age) => article
+                .published()
+                .is_some_and(|published| now.duration_since(published) > *age),
+        }
+    }
+
+    /// Collects the matching articles of every website, newest first
+    pub fn articles(&self, websites: &[Website], state: &State) -> Vec<Article> {
+        let now = Timestamp::now();
+        let mut articles: Vec<Article> = websites
+            .iter()
+            .flat_map(|website| {
+                website
+                    .articles
+                    .iter()
+                    .filter(move |article| self.matches(website, article, state, now))
+            })
+            .cloned()
+            .collect();
+
+        articles.sort_by_cached_key(|article| Reverse(article.published()));
+        articles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(query: &str) -> Vec<String> {
+        let article = |title: &str, author: &str, hours: i64| Article {
+            id: title.to_string(),
+            title: title.to_string(),
+            author: author.to_string(),
+            updated_at: (Timestamp::now() - SignedDuration::from_hours(hours)).to_string(),
+            ..Default::default()
+        };
+        let website = Website {
+            tags: vec!["Rust".to_string()],
+            articles: vec![
+                article("Rust 1.80 released", "Ann", 2),
+                article("A \"quoted\" title", "Bo", 30),
+                article("Weekly notes", "Charity Majors", 24 * 10),
+            ],
+            ..Default::default()
+        };
+        let mut state = State::default();
+        state.mark_read(&website.articles[1]);
+        let mut titles: Vec<String> = Query::parse(query)
+            .unwrap()
+            .articles(&[website], &state)
+            .into_iter()
+            .map(|article| article.title)
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("title~\"a \\\"b\\\"\" and(age<7d)").unwrap(),
+            [
+                Token::Word("title".to_string()),
+                Token::Tilde,
+                Token::Quoted("a \"b\"".to_string()),
+                Token::Word("and".to_string()),
+                Token::Open,
+                Token::Word("age".to_string()),
+                Token::Less,
+                Token::Word("7d".to_string()),
+                Token::Close,
+            ]
+        );
+        assert!(tokenize("title:\"open").is_err());
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            titles("author:ann or author:bo and unread"),
+            ["Rust 1.80 released"]
+        );
+        assert_eq!(
+            titles("(author:ann or author:bo) and read"),
+            ["A \"quoted\" title"]
+        );
+        assert_eq!(titles("not not author:charity"), ["Weekly notes"]);
+        assert_eq!(
+            titles("NOT author:charity AND tag:rust"),
+            ["A \"quoted\" title", "Rust 1.80 released"]
+        );
+    }
+
+    #[test]
+    fn fields_and_values() {
+        assert_eq!(titles("title:\"\\\"quoted\\\"\""), ["A \"quoted\" title"]);
+        assert_eq!(titles("author:\"charity majors\""), ["Weekly notes"]);
+        assert_eq!(titles("title~\"^rust [0-9.]+ \""), ["Rust 1.80 released"]);
+        assert_eq!(titles("age<1d"), ["Rust 1.80 released"]);
+        assert_eq!(titles("age>1w"), ["Weekly notes"]);
+    }
+
+    #[test]
+    fn invalid_queries_are_refused() {
+        for query in [
+            "",
+            "title",
+            "title:",
+            "colour:red",
+            "(unread",
+            "unread)",
+            "age=7d",
+            "age<7",
+            "age<-7d",
+            "title~\"(\"",
+        ] {
+            assert!(Query::parse(query).is_err(), "{query} was accepted");
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration("12h"), Ok(SignedDuration::from_hours(12)));
+        assert_eq!(duration("7d"), Ok(SignedDuration::from_hours(7 * 24)));
+        assert_eq!(duration("2w"), Ok(SignedDuration::from_hours(2 * 7 * 24)));
+        assert_eq!(duration("0d"), Ok(SignedDuration::ZERO));
+        for text in ["", "d", "-1d", "7", "7y", "1.5d", "99999999999d"] {
+            assert!(duration(text).is_err(), "{text} was accepted");
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use crate::{
+    config,
+    filter::Filter,
+    rss::{example_feed, Fetcher, StatusError, Website},
+};
+
+use jiff::{civil::Weekday, tz::TimeZone, SignedDuration, Timestamp};
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Longest the scheduler sleeps, so a clock jump or a suspended laptop doesn't delay refreshes
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Feeds fetched at the same time, so that a slow or dead feed doesn't hold up the others
+const FETCHERS: usize = 4;
+
+/// When a feed wants to be fetched again, declared by the feed itself and by the HTTP response
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Schedule {
+    /// RSS `<ttl>`, or `sy:updatePeriod` divided by `sy:updateFrequency`
+    pub ttl: Option<Duration>,
+    /// `max-age` of the `Cache-Control` header
+    pub max_age: Option<Duration>,
+    /// `Retry-After` header
+    pub retry_after: Option<Timestamp>,
+    /// RSS `<skipHours>`, hours of the day (in GMT) the feed should not be fetched
+    pub skip_hours: Vec<i8>,
+    /// RSS `<skipDays>`
+    pub skip_days: Vec<Weekday>,
+}
+
+impl Schedule {
+    /// Next time the feed should be fetched, never before the configured interval
+    pub fn next(&self, fetched_at: Timestamp, interval: Duration) -> Timestamp {
+        let wait = [Some(interval), self.ttl, self.max_age]
+            .into_iter()
+            .flatten()
+            .max()
+            .unwrap_or(interval);
+
+        let mut next = fetched_at
+            .checked_add(SignedDuration::try_from(wait).unwrap_or(SignedDuration::MAX))
+            .unwrap_or(Timestamp::MAX);
+        if let Some(retry_after) = self.retry_after {
+            next = next.max(retry_after);
+        }
+
+        // step over the skipped hours and days, one hour at a time for at most a week
+        for _ in 0..24 * 7 {
+            let utc = next.to_zoned(TimeZone::UTC);
+            if !self.skip_hours.contains(&utc.hour()) && !self.skip_days.contains(&utc.weekday()) {
+                break;
+            }
+            next = next
+                .checked_add(SignedDuration::from_hours(1))
+                .unwrap_or(Timestamp::MAX);
+        }
+
+        next
+    }
+}
+
+/// Parses the day names of `<skipDays>`
+pub(crate) fn weekday(name: &str) -> Option<Weekday> {
+    Some(match name.trim().to_lowercase().as_str() {
+        "monday" => Weekday::Monday,
+        "tuesday" => Weekday::Tuesday,
+        "wednesday" => Weekday::Wednesday,
+        "thursday" => Weekday::Thursday,
+        "friday" => Weekday::Friday,
+        "saturday" => Weekday::Saturday,
+        "sunday" => Weekday::Sunday,
+        _ => return None,
+    })
+}
+
+/// Sent by the refresh thread every time it fetched a feed, `requested` when it was fetched for
+/// `Refresher::refresh` rather than on schedule
+pub(crate) enum Update {
+    Fetched {
+        website: Box<Website>,
+        requested: bool,
+    },
+    Failed {
+        url: String,
+        error: String,
+        /// The server answered 410 Gone
+        gone: bool,
+        requested: bool,
+    },
+}
+
+/// Sent by the app and by the fetchers to the refresh thread
+enum Command {
+    Refresh(Vec<String>),
+    Pause(String),
+    /// A fetcher is done with a feed
+    Done {
+        job: Job,
+        update: Update,
+        schedule: Schedule,
+    },
+}
+
+/// A feed to fetch
+struct Job {
+    url: String,
+    requested: bool,
+}
+
+/// Handle on the refresh thread
+pub(crate) struct Refresher {
+    commands: Sender<Command>,
+    pub updates: Receiver<Update>,
+}
+
+impl Refresher {
+    /// Fetches the feeds right away, ahead of the ones due on schedule, paused feeds are
+    /// scheduled again
+    pub fn refresh(&self, urls: Vec<String>) {
+        // the thread only stops once the app is gone
+        let _ = self.commands.send(Command::Refresh(urls));
+    }
+
+    /// Stops fetching a feed until it is refreshed by hand
+    pub fn pause(&self, url: String) {
+        let _ = self.commands.send(Command::Pause(url));
+    }
+}
+
+/// Refresh interval of a subscription, `None` when it is not refreshed automatically
+fn interval(config: &config::Refresh, url: &str) -> Option<Duration> {
+    let minutes = config
+        .subscriptions
+        .get(url)
+        .copied()
+        .unwrap_or(config.interval);
+
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+/// Starts a thread fetching every subscription when it is due, or when asked to with
+/// `Refresher::refresh`, with `FETCHERS` feeds fetched at the same time
+pub(crate) fn spawn(
+    config: config::Refresh,
+    fetcher: Arc<Fetcher>,
+    filter: Arc<Filter>,
+    feeds: Vec<(String, Schedule)>,
+) -> Refresher {
+    let (sender, updates) = mpsc::channel();
+    let (commands, receiver) = mpsc::channel();
+    let (jobs, waiting) = mpsc::channel::<Job>();
+    let waiting = Arc::new(Mutex::new(waiting));
+
+    for _ in 0..FETCHERS {
+        let (waiting, done) = (waiting.clone(), commands.clone());
+        let (fetcher, filter) = (fetcher.clone(), filter.clone());
+        thread::spawn(move || loop {
+            // the lock is only held while waiting for the next job
+            let job = match waiting.lock() {
+                Ok(waiting) => waiting.recv(),
+                Err(_) => return,
+            };
+            let Ok(job) = job else {
+                return;
+            };
+
+            let (update, schedule) = fetch(&job, &fetcher, &filter);
+            let command = Command::Done {
+                job,
+                update,
+                schedule,
+            };
+            if done.send(command).is_err() {
+                return;
+            }
+        });
+    }
+
+    thread::spawn(move || {
+        let now = Timestamp::now();
+        let mut due: Vec<(String, Duration, Timestamp)> = feeds
+            .into_iter()
+            .filter_map(|(url, schedule)| {
+                let interval = interval(&config, &url)?;
+                let next = schedule.next(now, interval);
+                Some((url, interval, next))
+            })
+            .collect();
+        // feeds asked for come first, a feed is only fetched by one fetcher at a time
+        let mut queue: VecDeque<Job> = VecDeque::new();
+        let mut running: Vec<String> = vec![];
+
+        loop {
+            let now = Timestamp::now();
+            for (url, _, next) in due.iter_mut().filter(|(_, _, next)| *next <= now) {
+                if !queue.iter().any(|job| job.url == *url) && !running.contains(url) {
+                    queue.push_back(Job {
+                        url: url.clone(),
+                        requested: false,
+                    });
+                }
+                // scheduled again once fetched
+                *next = Timestamp::MAX;
+            }
+
+            while running.len() < FETCHERS {
+                let Some(position) = queue.iter().position(|job| !running.contains(&job.url))
+                else {
+                    break;
+                };
+                let job = queue.remove(position).expect("position is in the queue");
+                running.push(job.url.clone());
+                // the fetchers only stop once this thread is gone
+                let _ = jobs.send(job);
+            }
+
+            let sleep = due
+                .iter()
+                .map(|(_, _, next)| now.duration_until(*next))
+                .min()
+                .and_then(|wait| Duration::try_from(wait).ok())
+                .unwrap_or(MAX_SLEEP)
+                .clamp(Duration::from_secs(1), MAX_SLEEP);
+
+            match receiver.recv_timeout(sleep) {
+                Ok(Command::Refresh(urls)) => {
+                    let first_scheduled = queue
+                        .iter()
+                        .position(|job| !job.requested)
+                        .unwrap_or(queue.len());
+                    let mut asked = vec![];
+                    for url in urls {
+                        if queue.iter().any(|job| job.requested && job.url == url)
+                            || asked.iter().any(|job: &Job| job.url == url)
+                        {
+                            continue;
+                        }
+                        asked.push(Job {
+                            url,
+                            requested: true,
+                        });
+                    }
+                    queue.retain(|job| {
+                        job.requested || asked.iter().all(|asked| asked.url != job.url)
+                    });
+                    for (offset, job) in asked.into_iter().enumerate() {
+                        queue.insert(first_scheduled + offset, job);
+                    }
+                }
+                Ok(Command::Pause(url)) => {
+                    due.retain(|(due_url, _, _)| *due_url != url);
+                    // refreshes asked for still happen, the app waits for them
+                    queue.retain(|job| job.requested || job.url != url);
+                }
+                Ok(Command::Done {
+                    job,
+                    update,
+                    schedule,
+                }) => {
+                    running.retain(|url| *url != job.url);
+                    let now = Timestamp::now();
+                    match due.iter_mut().find(|(due_url, _, _)| *due_url == job.url) {
+                        Some((_, interval, next)) => *next = schedule.next(now, *interval),
+                        // a feed paused while it was fetched stays paused
+                        None if job.requested => {
+                            if let Some(interval) = interval(&config, &job.url) {
+                                let next = schedule.next(now, interval);
+                                due.push((job.url, interval, next));
+                            }
+                        }
+                        None => {}
+                    }
+                    // sent once the feed is scheduled again, so that a pause answering it comes
+                    // after
+                    if sender.send(update).is_err() {
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    });
+
+    Refresher { commands, updates }
+}
+
+/// Fetches a feed, returns the update to send and how to schedule the next fetch
+fn fetch(job: &Job, fetcher: &Fetcher, filter: &Filter) -> (Update, Schedule) {
+    match example_feed(&job.url, fetcher, filter) {
+        Ok(website) => {
+            let schedule = website.schedule.clone();
+            let update = Update::Fetched {
+                website: Box::new(website),
+                requested: job.requested,
+            };
+            (update, schedule)
+        }
+        // failures are tried again at the next interval, unless the server asked to wait
+        Err(error) => {
+            let schedule = Schedule {
+                retry_after: error
+                    .downcast_ref::<StatusError>()
+                    .and_then(|status| status.retry_after),
+                ..Default::default()
+            };
+            let update = Update::Failed {
+                url: job.url.clone(),
+                error: error.to_string(),
+                gone: error
+                    .downcast_ref::<StatusError>()
+                    .is_some_and(|status| status.status == 410),
+                requested: job.requested,
+            };
+            (update, schedule)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::{Response, Server};
+
+    fn at(time: &str) -> Timestamp {
+        time.parse().unwrap()
+    }
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn the_longest_wait_wins() {
+        let fetched_at = at("2024-01-01T12:00:00Z");
+        let mut schedule = Schedule::default();
+        assert_eq!(schedule.next(fetched_at, HOUR), at("2024-01-01T13:00:00Z"));
+
+        // a feed can ask to be fetched less often, never more often than configured
+        schedule.ttl = Some(6 * HOUR);
+        assert_eq!(schedule.next(fetched_at, HOUR), at("2024-01-01T18:00:00Z"));
+        assert_eq!(
+            schedule.next(fetched_at, 12 * HOUR),
+            at("2024-01-02T00:00:00Z")
+        );
+
+        schedule.max_age = Some(8 * HOUR);
+        schedule.retry_after = Some(at("2024-01-01T19:00:00Z"));
+        assert_eq!(schedule.next(fetched_at, HOUR), at("2024-01-01T20:00:00Z"));
+        schedule.retry_after = Some(at("2024-01-03T00:00:00Z"));
+        assert_eq!(schedule.next(fetched_at, HOUR), at("2024-01-03T00:00:00Z"));
+    }
+
+    #[test]
+    fn skipped_hours_and_days_are_stepped_over() {
+        let schedule = Schedule {
+            skip_hours: vec![13, 14],
+            // 2024-01-01 is a Monday
+            skip_days: vec![Weekday::Tuesday],
+            ..Default::default()
+        };
+        assert_eq!(
+            schedule.next(at("2024-01-01T12:00:00Z"), HOUR),
+            at("2024-01-01T15:00:00Z")
+        );
+        assert_eq!(
+            schedule.next(at("2024-01-01T23:00:00Z"), HOUR),
+            at("2024-01-03T00:00:00Z")
+        );
+
+        // a feed skipping every hour is still fetched, a week later
+        let never = Schedule {
+            skip_hours: (0..24).collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            never.next(at("2024-01-01T12:00:00Z"), HOUR),
+            at("2024-01-08T13:00:00Z")
+        );
+    }
+
+    #[test]
+    fn intervals_come_from_the_configuration() {
+        let config = config::Refresh {
+            interval: 30,
+            subscriptions: [
+                ("https://example.com/slow".to_string(), 120),
+                ("https://example.com/off".to_string(), 0),
+            ]
+            .into(),
+        };
+        assert_eq!(
+            interval(&config, "https://example.com/feed"),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            interval(&config, "https://example.com/slow"),
+            Some(2 * HOUR)
+        );
+        assert_eq!(interval(&config, "https://example.com/off"), None);
+        assert_eq!(
+            interval(
+                &config::Refresh {
+                    interval: 0,
+                    ..config
+                },
+                "https://example.com/feed"
+            ),
+            None
+        );
+    }
+
+    fn url(update: &Update) -> &str {
+        match update {
+            Update::Fetched { website, .. } => &website.url,
+            Update::Failed { url, .. } => url,
+        }
+    }
+
+    #[test]
+    fn a_refresh_does_not_wait_behind_a_slow_feed() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        // the slow feed only answers once released
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let released = released.clone();
+                thread::spawn(move || {
+                    if request.url() == "/slow" {
+                        let _ = released.lock().unwrap().recv();
+                    }
+                    let feed = "<rss version=\"2.0\"><channel><title>Feed</title>\
+                                <link>https://example.com/</link><description>d</description>\
+                                </channel></rss>";
+                    let _ = request.respond(Response::from_string(feed));
+                });
+            }
+        });
+
+        let fetcher = Fetcher::new(&config::Fetch {
+            retries: 0,
+            ..Default::default()
+        })
+        .unwrap();
+        let refresher = spawn(
+            config::Refresh::default(),
+            Arc::new(fetcher),
+            Arc::new(Filter::new(&[]).unwrap()),
+            vec![],
+        );
+        let (slow, fast) = (
+            format!("http://{}/slow", address),
+            format!("http://{}/fast", address),
+        );
+        refresher.refresh(vec![slow.clone()]);
+        refresher.refresh(vec![fast.clone()]);
+
+        let timeout = Duration::from_secs(10);
+        let first = refresher.updates.recv_timeout(timeout).unwrap();
+        assert_eq!(url(&first), fast);
+        assert!(matches!(
+            first,
+            Update::Fetched {
+                requested: true,
+                ..
+            }
+        ));
+
+        release.send(()).unwrap();
+        let second = refresher.updates.recv_timeout(timeout).unwrap();
+        assert_eq!(url(&second), slow);
+    }
+}
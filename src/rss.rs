@@ -1,15 +1,54 @@
-use atom_syndication::Feed;
-use rss::Channel;
-use std::{cmp::Ordering, error::Error};
+use crate::{
+    charset, config,
+    enclosure::{self, Enclosure},
+    error,
+    filter::Filter,
+    jsonfeed::JsonFeed,
+    lenient,
+    links::{join, resolve},
+    refresh::{weekday, Schedule},
+};
+
+use atom_syndication::{Feed, Text, TextType};
+use jiff::{fmt::rfc2822, SignedDuration, Timestamp};
+use regex::Regex;
+use reqwest::{
+    blocking::{Client, Response},
+    header::{HeaderMap, CACHE_CONTROL, CONTENT_TYPE, LOCATION, RETRY_AFTER},
+    redirect::Policy,
+    StatusCode, Url,
+};
+use rss::{extension::syndication::UpdatePeriod, Channel};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    fmt,
+    sync::LazyLock,
+    thread,
+    time::Duration,
+};
 
 #[derive(Debug)]
 enum FeedType {
-    Rss(Channel),
-    Atom(Feed),
+    Rss(Box<Channel>),
+    Atom(Box<Feed>),
+    Json(Box<JsonFeed>),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
 pub(crate) struct Website {
+    /// Address of the feed the website was fetched from
+    pub url: String,
+    /// Names of the folders the subscription is in
+    pub tags: Vec<String>,
+    /// When the feed asks to be fetched again
+    pub schedule: Schedule,
+    /// Where the feed moved for good, when every redirect on the way was a 301 or a 308
+    pub moved: Option<String>,
+    /// Repairs made to the feed so it could be read, see `lenient.rs`
+    pub repairs: Vec<String>,
     pub name: String,
     pub uri: String,
     pub author: String,
@@ -17,6 +56,17 @@ pub(crate) struct Website {
     pub articles: Vec<Article>,
 }
 
+impl Website {
+    /// Stands in for a subscription that was not fetched yet or could not be, so it still shows up
+    pub fn unreachable(url: &str) -> Website {
+        Website {
+            url: url.to_string(),
+            name: url.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
 impl Ord for Website {
     fn cmp(&self, other: &Self) -> Ordering {
         self.name.cmp(&other.name)
@@ -29,12 +79,35 @@ impl PartialOrd for Website {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
 pub(crate) struct Article {
+    /// Identifier used to remember the article, the guid or id when the feed has one
+    pub id: String,
+    /// Address of the feed the article was fetched from, ids are only unique within a feed
+    pub source: String,
+    /// Name of the website the article was published on
+    pub feed: String,
     pub title: String,
+    /// Address of the article on its website
+    pub link: Option<String>,
+    pub author: String,
     pub subtitle: Option<String>,
+    /// Description or summary as HTML, `subtitle` is its text on one line
+    pub description: Option<String>,
     pub updated_at: String,
     pub content: String,
+    pub categories: Vec<String>,
+    /// Audio, video or other files attached to the article
+    pub enclosures: Vec<Enclosure>,
+    /// Season and episode of a podcast, like `S2E5`
+    pub episode: Option<String>,
+    /// Language of the feed, like `en-US`, when it says
+    pub language: Option<String>,
+    /// Marked read by a filter rule
+    pub read: bool,
+    /// Color a filter rule highlights the article with
+    pub color: Option<String>,
 }
 
 impl Ord for Article {
@@ -49,65 +122,1027 @@ impl PartialOrd for Article {
     }
 }
 
-pub(crate) fn example_feed<'a>(url: &str) -> Result<Website, Box<dyn Error>> {
-    let content = reqwest::blocking::get(url)?.text()?;
+impl Article {
+    /// Identifies the article among those of every feed
+    pub fn key(&self) -> String {
+        format!("{} {}", self.source, self.id)
+    }
+
+    /// Parses `updated_at`, which is RFC 2822 for RSS and RFC 3339 for Atom
+    pub fn published(&self) -> Option<Timestamp> {
+        self.updated_at
+            .parse::<Timestamp>()
+            .ok()
+            .or_else(|| rfc2822::parse(&self.updated_at).ok().map(|z| z.timestamp()))
+    }
+}
+
+/// The server answered with an error status
+#[derive(Debug)]
+pub(crate) struct StatusError {
+    pub status: u16,
+    /// `Retry-After` header, sent with 429 and 503 responses
+    pub retry_after: Option<Timestamp>,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The server answered with status {}", self.status)
+    }
+}
+
+impl Error for StatusError {}
+
+/// Redirects followed before a fetch is given up
+const MAX_REDIRECTS: usize = 10;
+
+/// HTTP client shared by every fetch, retrying when the network or the server fails
+pub(crate) struct Fetcher {
+    client: Client,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Fetcher {
+    pub fn new(config: &config::Fetch) -> Result<Fetcher, error::Error> {
+        Ok(Fetcher {
+            // redirects are followed by hand to notice the feeds that moved
+            client: Client::builder()
+                .timeout(Duration::from_secs(config.timeout))
+                .redirect(Policy::none())
+                .build()?,
+            retries: config.retries,
+            backoff: Duration::from_millis(config.backoff),
+        })
+    }
+
+    /// GETs the URL, trying again after network errors, server errors and 429 responses
+    fn get(&self, url: &str) -> Result<Response, Box<dyn Error>> {
+        let mut attempt = 0;
+        loop {
+            match self.client.get(url).send() {
+                Ok(response)
+                    if response.status().is_success() || response.status().is_redirection() =>
+                {
+                    return Ok(response)
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let error = StatusError {
+                        status: status.as_u16(),
+                        retry_after: retry_after(response.headers()),
+                    };
+                    // a server saying when to come back is left to the scheduler
+                    let transient =
+                        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+                    if !transient || error.retry_after.is_some() || attempt >= self.retries {
+                        return Err(Box::new(error));
+                    }
+                }
+                Err(error) => {
+                    let transient = error.is_timeout() || error.is_connect() || error.is_request();
+                    if !transient || attempt >= self.retries {
+                        return Err(Box::new(error));
+                    }
+                }
+            }
+
+            thread::sleep(self.delay(attempt));
+            attempt += 1;
+        }
+    }
+
+    /// Follows the redirects, returns the final response and where the feed moved for good
+    pub fn follow(&self, url: &str) -> Result<(Response, Option<String>), Box<dyn Error>> {
+        let original = Url::parse(url)?;
+        let mut current = original.clone();
+        let mut permanent = true;
+
+        for _ in 0..MAX_REDIRECTS {
+            let response = self.get(current.as_str())?;
+            let status = response.status();
+            if !status.is_redirection() {
+                let moved = (permanent && current != original).then(|| current.to_string());
+                return Ok((response, moved));
+            }
+
+            permanent &= matches!(
+                status,
+                StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+            );
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or(StatusError {
+                    status: status.as_u16(),
+                    retry_after: None,
+                })?;
+            current = current.join(location)?;
+        }
+
+        Err(format!("More than {} redirects", MAX_REDIRECTS).into())
+    }
+
+    /// Exponential backoff, with up to half of it added at random so that feeds failing together
+    /// don't retry together
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.backoff.saturating_mul(2u32.saturating_pow(attempt));
+        delay + delay.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// Whether the text is a feed trss can read
+pub(crate) fn is_feed(content: &str) -> bool {
+    Channel::read_from(content.as_bytes()).is_ok()
+        || Feed::read_from(content.as_bytes()).is_ok()
+        || JsonFeed::parse(content).is_ok()
+}
+
+/// Start or end of an element, or a comment
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<!--.*?-->|<(/?)([A-Za-z][A-Za-z0-9]*)(\s[^<>]*)?/?>").unwrap());
+
+/// HTML elements found in titles and summaries. Other names are more likely text, like the
+/// `<String>` of `Vec<String>`.
+const ELEMENTS: [&str; 36] = [
+    "a",
+    "abbr",
+    "b",
+    "big",
+    "blockquote",
+    "br",
+    "cite",
+    "code",
+    "del",
+    "dfn",
+    "div",
+    "em",
+    "font",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "p",
+    "q",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "time",
+    "tt",
+    "u",
+];
+
+/// Elements that never have an end tag
+const VOID: [&str; 3] = ["br", "img", "wbr"];
+
+/// Drops the tags of HTML elements. A known name alone is not enough, `Option<A>` is text: the
+/// tag also needs attributes, an end tag somewhere in the text, or to be one of the void elements.
+fn strip_tags(text: &str) -> String {
+    let ended: HashSet<String> = TAG
+        .captures_iter(text)
+        .filter(|tag| tag.get(1).is_some_and(|slash| !slash.is_empty()))
+        .filter_map(|tag| Some(tag.get(2)?.as_str().to_lowercase()))
+        .collect();
+
+    TAG.replace_all(text, |tag: &regex::Captures| {
+        let Some(name) = tag.get(2).map(|name| name.as_str().to_lowercase()) else {
+            // a comment
+            return String::new();
+        };
+        let markup = ELEMENTS.contains(&name.as_str())
+            && (tag.get(3).is_some() || VOID.contains(&name.as_str()) || ended.contains(&name));
+        if !markup {
+            tag[0].to_string()
+        } else if matches!(name.as_str(), "br" | "p" | "div" | "li" | "blockquote") {
+            // line breaks and blocks still part words
+            " ".to_string()
+        } else {
+            String::new()
+        }
+    })
+    .into_owned()
+}
+
+/// Turns a title, summary or name into plain text on a single line, markup is dropped and its
+/// entities decoded
+fn plain_text(text: &str, markup: bool) -> String {
+    let text = if markup {
+        html_escape::decode_html_entities(&strip_tags(text)).into_owned()
+    } else {
+        text.to_string()
+    };
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Atom text constructs declare whether they hold text, HTML or XHTML
+fn atom_text(text: &Text) -> String {
+    plain_text(&text.value, text.r#type != TextType::Text)
+}
+
+/// An Atom text construct as HTML
+fn atom_html(text: &Text) -> String {
+    match text.r#type {
+        TextType::Text => html_escape::encode_text(&text.value).into_owned(),
+        TextType::Html | TextType::Xhtml => text.value.clone(),
+    }
+}
+
+/// Publication date of an entry, else its last update; empty when the entry has neither, as the
+/// Atom crate stands a missing `<updated>` in with the epoch
+fn atom_date(entry: &atom_syndication::Entry) -> String {
+    entry
+        .published()
+        .or(Some(entry.updated()).filter(|updated| updated.timestamp() != 0))
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// An XML extension element, the RSS and Atom crates each have their own with the same shape
+trait Extension: Sized {
+    fn attrs(&self) -> &BTreeMap<String, String>;
+    fn children(&self) -> &BTreeMap<String, Vec<Self>>;
+}
+
+impl Extension for rss::extension::Extension {
+    fn attrs(&self) -> &BTreeMap<String, String> {
+        &self.attrs
+    }
+
+    fn children(&self) -> &BTreeMap<String, Vec<Self>> {
+        &self.children
+    }
+}
+
+impl Extension for atom_syndication::extension::Extension {
+    fn attrs(&self) -> &BTreeMap<String, String> {
+        &self.attrs
+    }
+
+    fn children(&self) -> &BTreeMap<String, Vec<Self>> {
+        &self.children
+    }
+}
+
+/// Attributes of the Media RSS `<media:content>` of an RSS item or an Atom entry
+fn media<E: Extension>(
+    extensions: &BTreeMap<String, BTreeMap<String, Vec<E>>>,
+) -> Vec<BTreeMap<String, String>> {
+    let Some(media) = extensions.get("media") else {
+        return vec![];
+    };
+    let grouped = media
+        .get("group")
+        .into_iter()
+        .flatten()
+        .flat_map(|group| group.children().get("content").into_iter().flatten());
+
+    media
+        .get("content")
+        .into_iter()
+        .flatten()
+        .chain(grouped)
+        .map(|content| content.attrs().clone())
+        .collect()
+}
+
+/// Enclosures of an article resolved against its base, a file listed twice (as an enclosure and
+/// in Media RSS) is only kept once
+fn enclosures(found: impl Iterator<Item = Enclosure>, base: &Url) -> Vec<Enclosure> {
+    let mut enclosures: Vec<Enclosure> = vec![];
+    for mut enclosure in found {
+        enclosure.url = join(base, Some(&enclosure.url)).to_string();
+        if enclosures.iter().all(|e| e.url != enclosure.url) {
+            enclosures.push(enclosure);
+        }
+    }
+    enclosures
+}
+
+/// `S2E5` from the season and episode numbers of a podcast, `E5` without a season
+fn episode(season: Option<&str>, episode: Option<&str>) -> Option<String> {
+    let episode = episode?.trim();
+    Some(match season.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(season) => format!("S{}E{}", season, episode),
+        None => format!("E{}", episode),
+    })
+}
+
+/// Language tag of a feed, like `en-US`
+fn language(tag: Option<&str>) -> Option<String> {
+    tag.map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+}
+
+/// JSON Feeds are told apart by their content type, or by the body when the server says nothing
+/// useful
+fn is_json(headers: &HeaderMap, content: &str) -> bool {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default();
+
+    content_type.contains("json")
+        || content
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .starts_with('{')
+}
+
+/// `max-age` of the `Cache-Control` header
+fn max_age(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(CACHE_CONTROL)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find_map(|directive| directive.trim().strip_prefix("max-age=")?.parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// `Retry-After` header, either a number of seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Timestamp> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse::<i64>() {
+        Ok(seconds) => Timestamp::now()
+            .checked_add(SignedDuration::from_secs(seconds))
+            .ok(),
+        Err(_) => rfc2822::parse(value).ok().map(|date| date.timestamp()),
+    }
+}
+
+/// Refresh schedule declared by an RSS channel
+fn channel_schedule(channel: &Channel) -> Schedule {
+    let ttl = channel
+        .ttl()
+        .and_then(|minutes| minutes.trim().parse::<u64>().ok())
+        .map(|minutes| Duration::from_secs(minutes * 60));
+
+    let update_period = channel.syndication_ext().map(|sy| {
+        let period = match sy.period() {
+            UpdatePeriod::Hourly => 60 * 60,
+            UpdatePeriod::Daily => 24 * 60 * 60,
+            UpdatePeriod::Weekly => 7 * 24 * 60 * 60,
+            UpdatePeriod::Monthly => 30 * 24 * 60 * 60,
+            UpdatePeriod::Yearly => 365 * 24 * 60 * 60,
+        };
+        Duration::from_secs(period / u64::from(sy.frequency().max(1)))
+    });
+
+    Schedule {
+        ttl: ttl.max(update_period),
+        skip_hours: channel
+            .skip_hours()
+            .iter()
+            .filter_map(|hour| hour.trim().parse().ok())
+            .collect(),
+        skip_days: channel
+            .skip_days()
+            .iter()
+            .filter_map(|day| weekday(day))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Name of the root element of an XML document, without its namespace prefix
+fn root(content: &str) -> Option<&str> {
+    let tag = Regex::new(r"<([A-Za-z_][\w.:-]*)").unwrap();
+    let name = tag.captures(content)?.get(1)?.as_str();
+    name.rsplit(':').next()
+}
+
+/// Parses an RSS 0.9x, 1.0 (RDF) or 2.0 document, or an Atom one
+fn parse_xml(content: &str) -> Result<FeedType, Box<dyn Error>> {
+    Ok(match root(content) {
+        Some("feed") => FeedType::Atom(Box::new(Feed::read_from(content.as_bytes())?)),
+        Some("rss" | "RDF") => FeedType::Rss(Box::new(Channel::read_from(content.as_bytes())?)),
+        _ => match Channel::read_from(content.as_bytes()) {
+            Ok(c) => FeedType::Rss(Box::new(c)),
+            Err(_) => FeedType::Atom(Box::new(Feed::read_from(content.as_bytes())?)),
+        },
+    })
+}
+
+/// Parses the feed, repairing it when it is malformed, returns the feed and the repairs made
+fn parse(headers: &HeaderMap, content: &str) -> Result<(FeedType, Vec<String>), Box<dyn Error>> {
+    if is_json(headers, content) {
+        let feed = JsonFeed::parse(content.trim_start_matches('\u{feff}'))?;
+        return Ok((FeedType::Json(Box::new(feed)), vec![]));
+    }
 
-    let feed: FeedType = match Channel::read_from(content.as_bytes()) {
-        Ok(c) => FeedType::Rss(c),
-        Err(_) => FeedType::Atom(Feed::read_from(content.as_bytes()).unwrap()),
+    let error = match parse_xml(content) {
+        Ok(feed) => return Ok((feed, vec![])),
+        Err(error) => error,
     };
 
-    Ok(match feed {
+    // the error of the feed as it was served says more than the one of the repaired feed
+    let (repaired, repairs) = lenient::repair(content);
+    if repairs.is_empty() {
+        return Err(error);
+    }
+    let feed = parse_xml(&repaired).map_err(|_| error)?;
+    Ok((feed, repairs.into_iter().map(str::to_string).collect()))
+}
+
+pub(crate) fn example_feed(
+    url: &str,
+    fetcher: &Fetcher,
+    filter: &Filter,
+) -> Result<Website, Box<dyn Error>> {
+    let (response, moved) = fetcher.follow(url)?;
+    // relative links are resolved against where the feed was actually served from
+    let base = response.url().clone();
+    let headers = response.headers().clone();
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok());
+    let content = charset::decode(&response.bytes()?, content_type);
+
+    let (feed, repairs) = parse(&headers, &content)?;
+
+    let mut website = website(url, base, feed);
+
+    website.moved = moved;
+    website.repairs = repairs;
+    website.schedule.max_age = max_age(&headers);
+    website.schedule.retry_after = retry_after(&headers);
+    filter.apply(url, &mut website);
+
+    Ok(website)
+}
+
+/// The website of a parsed feed, with its links resolved against `base`
+fn website(url: &str, base: Url, feed: FeedType) -> Website {
+    match feed {
         FeedType::Rss(content) => Website {
-            name: content.title().to_string(),
-            uri: content.link().to_string(),
-            author: content.managing_editor().unwrap_or("N/A").to_string(),
-            updated_at: content.last_build_date().unwrap_or("N/A").to_string(),
+            url: url.to_string(),
+            tags: vec![],
+            schedule: channel_schedule(&content),
+            moved: None,
+            repairs: vec![],
+            // RSS titles and descriptions may hold escaped HTML
+            name: plain_text(content.title(), true),
+            uri: join(&base, Some(content.link())).to_string(),
+            author: content
+                .managing_editor()
+                .map(|author| plain_text(author, true))
+                .unwrap_or_else(|| "N/A".to_string()),
+            // RSS 1.0 dates its channel and items with Dublin Core
+            updated_at: content
+                .last_build_date()
+                .or(content
+                    .dublin_core_ext()
+                    .and_then(|dc| dc.dates().first())
+                    .map(String::as_str))
+                .unwrap_or("N/A")
+                .to_string(),
             articles: content
                 .items()
                 .iter()
-                .map(|item| Article {
-                    title: item.title().unwrap_or("Untitled").to_string(),
-                    subtitle: Some(item.description().unwrap_or("").to_string()),
-                    updated_at: item.pub_date().unwrap_or("N/A").to_string(),
-                    content: item.content().unwrap_or("N/A").to_string(),
+                .map(|item| (item, join(&base, item.link())))
+                .map(|(item, base)| Article {
+                    source: url.to_string(),
+                    id: item
+                        .guid()
+                        .map(|guid| guid.value())
+                        .or(item.link())
+                        .or(item.title())
+                        .unwrap_or_default()
+                        .to_string(),
+                    feed: plain_text(content.title(), true),
+                    title: plain_text(item.title().unwrap_or("Untitled"), true),
+                    link: item.link().map(|_| base.to_string()),
+                    author: item
+                        .author()
+                        .map(|author| plain_text(author, true))
+                        .or_else(|| {
+                            item.dublin_core_ext()
+                                .map(|dc| plain_text(&dc.creators().join(", "), true))
+                                .filter(|creators| !creators.is_empty())
+                        })
+                        .unwrap_or_default(),
+                    subtitle: Some(plain_text(item.description().unwrap_or(""), true)),
+                    description: item
+                        .description()
+                        .map(|description| resolve(description, &base)),
+                    updated_at: item
+                        .pub_date()
+                        .or(item
+                            .dublin_core_ext()
+                            .and_then(|dc| dc.dates().first())
+                            .map(String::as_str))
+                        .unwrap_or("N/A")
+                        .to_string(),
+                    // RSS 1.0 items rarely have more than a description, podcasts keep their
+                    // show notes in the iTunes summary
+                    content: resolve(
+                        item.content()
+                            .or(item.description())
+                            .or(item.itunes_ext().and_then(|itunes| itunes.summary()))
+                            .unwrap_or("N/A"),
+                        &base,
+                    ),
+                    categories: item
+                        .categories()
+                        .iter()
+                        .map(|category| category.name().to_string())
+                        .collect(),
+                    enclosures: enclosures(
+                        item.enclosure()
+                            .map(|found| Enclosure {
+                                url: found.url().to_string(),
+                                mime_type: found.mime_type().to_string(),
+                                length: found.length().trim().parse().ok(),
+                                duration: item
+                                    .itunes_ext()
+                                    .and_then(|itunes| itunes.duration())
+                                    .and_then(enclosure::duration),
+                            })
+                            .into_iter()
+                            .chain(enclosure::media(media(item.extensions()).into_iter())),
+                        &base,
+                    ),
+                    episode: item
+                        .itunes_ext()
+                        .and_then(|itunes| episode(itunes.season(), itunes.episode())),
+                    language: language(
+                        content.language().or(content
+                            .dublin_core_ext()
+                            .and_then(|dc| dc.languages().first())
+                            .map(String::as_str)),
+                    ),
+                    read: false,
+                    color: None,
                 })
                 .collect(),
         },
-        FeedType::Atom(content) => Website {
-            name: content.title().value.clone(),
+        FeedType::Atom(content) => {
+            let feed_base = join(&base, content.base());
+            Website {
+                url: url.to_string(),
+                tags: vec![],
+                schedule: Schedule::default(),
+                moved: None,
+                repairs: vec![],
+                name: atom_text(content.title()),
+                uri: content
+                    .links()
+                    .iter()
+                    .find(|link| link.rel() == "alternate")
+                    .map(|link| join(&feed_base, Some(link.href())).to_string())
+                    .unwrap_or_default(),
+                author: content
+                    .authors()
+                    .iter()
+                    .map(|author| plain_text(&author.name, false))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                updated_at: content.updated().to_string(),
+                articles: content
+                    .entries()
+                    .iter()
+                    .map(|item| {
+                        // an explicit xml:base wins over the link of the entry
+                        let base = if content.base().is_some() {
+                            feed_base.clone()
+                        } else {
+                            join(
+                                &feed_base,
+                                item.links()
+                                    .iter()
+                                    .find(|link| link.rel() == "alternate")
+                                    .map(|link| link.href()),
+                            )
+                        };
+                        (item, base)
+                    })
+                    .map(|(item, base)| Article {
+                        source: url.to_string(),
+                        id: item.id().to_string(),
+                        feed: atom_text(content.title()),
+                        title: atom_text(item.title()),
+                        link: item
+                            .links()
+                            .iter()
+                            .find(|link| link.rel() == "alternate")
+                            .map(|link| join(&feed_base, Some(link.href())).to_string()),
+                        author: item
+                            .authors()
+                            .iter()
+                            .map(|author| plain_text(&author.name, false))
+                            .collect::<Vec<String>>()
+                            .join(", "),
+                        subtitle: item.summary().map(atom_text),
+                        description: item
+                            .summary()
+                            .map(|summary| resolve(&atom_html(summary), &base)),
+                        updated_at: atom_date(item),
+                        content: item
+                            .content()
+                            .and_then(|content| {
+                                let value = content.value()?;
+                                Some(resolve(value, &join(&base, content.base())))
+                            })
+                            .unwrap_or("N/A".to_string()),
+                        categories: item
+                            .categories()
+                            .iter()
+                            .map(|category| category.label().unwrap_or(category.term()).to_string())
+                            .collect(),
+                        enclosures: enclosures(
+                            item.links()
+                                .iter()
+                                .filter(|link| link.rel() == "enclosure")
+                                .map(|link| Enclosure {
+                                    url: link.href().to_string(),
+                                    mime_type: link.mime_type().unwrap_or_default().to_string(),
+                                    length: link.length().and_then(|length| length.parse().ok()),
+                                    duration: None,
+                                })
+                                .chain(enclosure::media(media(item.extensions()).into_iter())),
+                            &base,
+                        ),
+                        episode: None,
+                        language: language(content.lang()),
+                        read: false,
+                        color: None,
+                    })
+                    .collect(),
+            }
+        }
+        FeedType::Json(content) => Website {
+            url: url.to_string(),
+            tags: vec![],
+            schedule: Schedule::default(),
+            moved: None,
+            repairs: vec![],
+            // JSON Feed titles and summaries are plain text
+            name: plain_text(&content.title, false),
             uri: content
-                .links()
-                .iter()
-                .map(|link| link.href.clone())
-                .collect::<Vec<String>>()
-                .join(" "),
-            author: content
-                .authors()
+                .home_page_url
+                .as_deref()
+                .map(|home| join(&base, Some(home)).to_string())
+                .unwrap_or_default(),
+            author: content.author_names(),
+            updated_at: content
+                .items
                 .iter()
-                .map(|author| author.name.clone())
-                .collect::<Vec<String>>()
-                .join(" "),
-            updated_at: content.updated().to_string(),
+                .filter_map(|item| item.date_modified.as_ref().or(item.date_published.as_ref()))
+                .max()
+                .cloned()
+                .unwrap_or("N/A".to_string()),
             articles: content
-                .entries()
+                .items
                 .iter()
-                .map(|item| Article {
-                    title: item.title().value.clone(),
-                    subtitle: Some(item.summary().cloned().unwrap_or_default().value),
+                .map(|item| (item, join(&base, item.url.as_deref())))
+                .map(|(item, base)| Article {
+                    source: url.to_string(),
+                    id: item.id(),
+                    feed: plain_text(&content.title, false),
+                    title: plain_text(item.title.as_deref().unwrap_or("Untitled"), false),
+                    link: item.url.as_ref().map(|_| base.to_string()),
+                    author: Some(item.author_names())
+                        .filter(|author| !author.is_empty())
+                        .unwrap_or_else(|| content.author_names()),
+                    subtitle: item
+                        .summary
+                        .as_deref()
+                        .map(|summary| plain_text(summary, false)),
+                    description: item
+                        .summary
+                        .as_deref()
+                        .map(|summary| html_escape::encode_text(summary).into_owned()),
                     updated_at: item
-                        .published()
-                        .map(|time| time.to_rfc3339())
-                        .unwrap_or_else(|| jiff::Timestamp::now().to_string()),
-                    content: item
-                        .content()
-                        .cloned()
-                        .unwrap_or_default()
-                        .value
-                        .unwrap_or("N/A".to_string())
-                        .to_string(),
+                        .date_published
+                        .clone()
+                        .or(item.date_modified.clone())
+                        .unwrap_or_default(),
+                    content: resolve(&item.content(), &base),
+                    categories: item.tags.clone(),
+                    enclosures: enclosures(
+                        item.attachments.iter().map(|attachment| Enclosure {
+                            url: attachment.url.clone(),
+                            mime_type: attachment.mime_type.clone(),
+                            length: attachment.size_in_bytes,
+                            duration: attachment.duration_in_seconds.map(|seconds| seconds as u64),
+                        }),
+                        &base,
+                    ),
+                    episode: None,
+                    language: language(content.language.as_deref()),
+                    read: false,
+                    color: None,
                 })
                 .collect(),
         },
-    })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state::State, timeline::Timeline};
+    use jiff::civil::Weekday;
+
+    fn read(content: &str) -> Website {
+        let (feed, _) = parse(&HeaderMap::new(), content).unwrap();
+        website(
+            "https://example.com/feed",
+            Url::parse("https://example.com/feed").unwrap(),
+            feed,
+        )
+    }
+
+    fn rss_titles(titles: &[&str]) -> Vec<String> {
+        let items: String = titles
+            .iter()
+            .map(|title| {
+                format!(
+                    "<item><title>{}</title><guid>{}</guid></item>",
+                    title, title
+                )
+            })
+            .collect();
+        let website = read(&format!(
+            "<rss version=\"2.0\"><channel><title>Feed</title><link>https://example.com/</link>\
+             <description>d</description>{}</channel></rss>",
+            items
+        ));
+        website.articles.into_iter().map(|a| a.title).collect()
+    }
+
+    fn atom_title(kind: &str, title: &str) -> String {
+        read(&format!(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>Feed</title><id>f</id>\
+             <updated>2024-01-01T00:00:00Z</updated><entry><id>1</id>\
+             <title type=\"{}\">{}</title><updated>2024-01-01T00:00:00Z</updated></entry></feed>",
+            kind, title
+        ))
+        .articles
+        .remove(0)
+        .title
+    }
+
+    #[test]
+    fn rss_titles_drop_escaped_html() {
+        assert_eq!(
+            rss_titles(&[
+                "&lt;em&gt;New&lt;/em&gt; release",
+                "Line&lt;br&gt;break",
+                "&lt;a href=\"https://example.com\"&gt;Link&lt;/a&gt; &amp;amp; more",
+            ]),
+            ["New release", "Line break", "Link & more"]
+        );
+    }
+
+    #[test]
+    fn rss_titles_keep_generics() {
+        assert_eq!(
+            rss_titles(&[
+                "Vec&lt;String&gt; is fast",
+                "Option&lt;T&gt;",
+                "Why Vec&lt;A&gt; and &lt;b&gt;Box&lt;dyn Fn&gt;&lt;/b&gt;",
+            ]),
+            [
+                "Vec<String> is fast",
+                "Option<T>",
+                "Why Vec<A> and Box<dyn Fn>"
+            ]
+        );
+    }
+
+    #[test]
+    fn atom_titles_follow_their_type() {
+        assert_eq!(
+            atom_title("text", "Vec&lt;String&gt; &amp; &lt;b&gt;"),
+            "Vec<String> & <b>"
+        );
+        assert_eq!(
+            atom_title("html", "&lt;b&gt;Bold&lt;/b&gt; Option&lt;T&gt; &amp;amp;"),
+            "Bold Option<T> &"
+        );
+        assert_eq!(
+            atom_title(
+                "xhtml",
+                "<div xmlns=\"http://www.w3.org/1999/xhtml\"><b>Bold</b> and <i>italic</i></div>"
+            ),
+            "Bold and italic"
+        );
+    }
+
+    #[test]
+    fn media_content_of_both_formats() {
+        let media = "<media:content url=\"a.mp3\" type=\"audio/mpeg\"/>\
+            <media:group><media:content url=\"b.mp4\" type=\"video/mp4\"/></media:group>";
+        let rss = read(&format!(
+            "<rss version=\"2.0\" xmlns:media=\"http://search.yahoo.com/mrss/\"><channel>\
+             <title>Feed</title><link>https://example.com/</link><description>d</description>\
+             <item><title>One</title>{}</item></channel></rss>",
+            media
+        ));
+        let atom = read(&format!(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:media=\"http://search.yahoo.com/mrss/\">\
+             <title>Feed</title><id>f</id><updated>2024-01-01T00:00:00Z</updated><entry><id>1</id>\
+             <title>One</title><updated>2024-01-01T00:00:00Z</updated>{}</entry></feed>",
+            media
+        ));
+        for website in [rss, atom] {
+            let urls: Vec<&str> = website.articles[0]
+                .enclosures
+                .iter()
+                .map(|enclosure| enclosure.url.as_str())
+                .collect();
+            assert_eq!(
+                urls,
+                ["https://example.com/a.mp3", "https://example.com/b.mp4"]
+            );
+        }
+    }
+
+    #[test]
+    fn atom_entries_are_dated_by_their_update() {
+        let now = Timestamp::from_second(Timestamp::now().as_second()).unwrap();
+        let website = read(&format!(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>Feed</title><id>f</id>\
+             <updated>{now}</updated><entry><id>1</id><title>Updated</title>\
+             <updated>{now}</updated></entry><entry><id>2</id><title>Old</title>\
+             <updated>2001-01-01T00:00:00Z</updated></entry><entry><id>3</id>\
+             <title>Undated</title></entry></feed>"
+        ));
+        assert_eq!(website.articles[0].published(), Some(now));
+        assert_eq!(website.articles[2].published(), None);
+
+        let today: Vec<String> = Timeline::Today
+            .articles(&[website], &State::default())
+            .into_iter()
+            .map(|article| article.title)
+            .collect();
+        assert_eq!(today, ["Updated"]);
+    }
+
+    #[test]
+    fn languages_come_from_the_feed() {
+        let rss = read(
+            "<rss version=\"2.0\"><channel><title>Feed</title><link>https://example.com/</link>\
+             <description>d</description><language> fr-CA </language><item><title>One</title>\
+             </item></channel></rss>",
+        );
+        let atom = read(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"de\"><title>Feed</title>\
+             <id>f</id><entry><id>1</id><title>One</title></entry></feed>",
+        );
+        let json = read(
+            "{\"version\": \"https://jsonfeed.org/version/1.1\", \"title\": \"Feed\", \
+             \"language\": \"ja\", \"items\": [{\"id\": \"1\"}]}",
+        );
+        let languages: Vec<Option<&str>> = [&rss, &atom, &json]
+            .iter()
+            .map(|website| website.articles[0].language.as_deref())
+            .collect();
+        assert_eq!(languages, [Some("fr-CA"), Some("de"), Some("ja")]);
+    }
+
+    #[test]
+    fn links_are_resolved_against_xml_base() {
+        let website = read(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:base=\"https://example.org/blog/\">\
+             <title>Feed</title><id>f</id><link href=\"index.html\"/><entry><id>1</id>\
+             <title>One</title><link href=\"posts/one\"/>\
+             <content type=\"html\" xml:base=\"images/\">&lt;img src=\"a.png\"&gt;</content>\
+             </entry><entry><id>2</id><title>Two</title><link href=\"/two\"/>\
+             <content type=\"html\">&lt;a href=\"#top\"&gt;top&lt;/a&gt;</content></entry></feed>",
+        );
+        assert_eq!(website.uri, "https://example.org/blog/index.html");
+        let one = &website.articles[0];
+        assert_eq!(
+            one.link.as_deref(),
+            Some("https://example.org/blog/posts/one")
+        );
+        assert_eq!(
+            one.content,
+            "<img src=\"https://example.org/blog/images/a.png\">"
+        );
+        let two = &website.articles[1];
+        assert_eq!(two.link.as_deref(), Some("https://example.org/two"));
+        assert_eq!(two.content, "<a href=\"#top\">top</a>");
+    }
+
+    #[test]
+    fn rss_links_are_resolved_against_the_feed() {
+        let website = read(
+            "<rss version=\"2.0\"><channel><title>Feed</title><link>/</link>\
+             <description>d</description><item><title>One</title><link>posts/one</link>\
+             <description>&lt;img src=\"a.png\"&gt;</description></item></channel></rss>",
+        );
+        assert_eq!(website.uri, "https://example.com/");
+        assert_eq!(
+            website.articles[0].link.as_deref(),
+            Some("https://example.com/posts/one")
+        );
+    }
+
+    #[test]
+    fn channels_declare_their_schedule() {
+        let schedule = |extra: &str| {
+            read(&format!(
+                "<rss version=\"2.0\" xmlns:sy=\"http://purl.org/rss/1.0/modules/syndication/\">\
+                 <channel><title>Feed</title><link>https://example.com/</link>\
+                 <description>d</description>{}</channel></rss>",
+                extra
+            ))
+            .schedule
+        };
+        let hours = |hours: u64| Some(Duration::from_secs(hours * 60 * 60));
+
+        assert_eq!(schedule(""), Schedule::default());
+        assert_eq!(
+            schedule("<ttl>90</ttl>").ttl,
+            Some(Duration::from_secs(90 * 60))
+        );
+        assert_eq!(
+            schedule(
+                "<sy:updatePeriod>daily</sy:updatePeriod>\
+                 <sy:updateFrequency>4</sy:updateFrequency>"
+            )
+            .ttl,
+            hours(6)
+        );
+        assert_eq!(
+            schedule("<sy:updatePeriod>weekly</sy:updatePeriod>").ttl,
+            hours(7 * 24)
+        );
+        // the longest of the two
+        assert_eq!(
+            schedule(
+                "<ttl>60</ttl><sy:updatePeriod>hourly</sy:updatePeriod>\
+                      <sy:updateFrequency>2</sy:updateFrequency>"
+            )
+            .ttl,
+            hours(1)
+        );
+        assert_eq!(
+            schedule("<ttl>600</ttl><sy:updatePeriod>daily</sy:updatePeriod>").ttl,
+            hours(24)
+        );
+
+        let skipped = schedule(
+            "<skipHours><hour>0</hour><hour>23</hour></skipHours>\
+             <skipDays><day>Saturday</day><day>sunday</day><day>never</day></skipDays>",
+        );
+        assert_eq!(skipped.skip_hours, [0, 23]);
+        assert_eq!(skipped.skip_days, [Weekday::Saturday, Weekday::Sunday]);
+    }
+
+    #[test]
+    fn descriptions_keep_their_html() {
+        let rss = read(
+            "<rss version=\"2.0\"><channel><title>Feed</title><link>https://example.com/</link>\
+             <description>d</description><item><title>One</title>\
+             <description>&lt;p&gt;First &lt;a href=\"/a\"&gt;link&lt;/a&gt;&lt;/p&gt;\
+             &lt;p&gt;Second&lt;/p&gt;</description></item></channel></rss>",
+        );
+        let article = &rss.articles[0];
+        assert_eq!(article.subtitle.as_deref(), Some("First link Second"));
+        assert_eq!(
+            article.description.as_deref(),
+            Some("<p>First <a href=\"https://example.com/a\">link</a></p><p>Second</p>")
+        );
+
+        let atom = read(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>Feed</title><id>f</id>\
+             <entry><id>1</id><title>One</title><summary>1 &lt; 2</summary></entry>\
+             <entry><id>2</id><title>Two</title><summary type=\"html\">&lt;p&gt;Hi&lt;/p&gt;\
+             </summary></entry></feed>",
+        );
+        let descriptions: Vec<Option<&str>> = atom
+            .articles
+            .iter()
+            .map(|article| article.description.as_deref())
+            .collect();
+        assert_eq!(descriptions, [Some("1 &lt; 2"), Some("<p>Hi</p>")]);
+    }
+
+    #[test]
+    fn rss_channel_author_is_plain_text() {
+        let website = read(
+            "<rss version=\"2.0\"><channel><title>Feed</title><link>https://example.com/</link>\
+             <description>d</description><managingEditor>ann@example.com\n  (Ann &amp;amp; \
+             &lt;b&gt;Bo&lt;/b&gt;)</managingEditor></channel></rss>",
+        );
+        assert_eq!(website.author, "ann@example.com (Ann & Bo)");
+    }
 }
@@ -0,0 +1,169 @@
+use crate::rss::{Article, Website};
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
+    ops::{Bound, Range},
+};
+
+/// Inverted index of the words in the titles, authors and bodies of every fetched article
+pub(crate) struct Index {
+    /// Maps a lowercase word to the documents containing it, in increasing order
+    postings: BTreeMap<String, Vec<usize>>,
+    /// Position of each document as (website, article) indices
+    documents: Vec<(usize, usize)>,
+}
+
+impl Index {
+    pub fn new(websites: &[Website]) -> Index {
+        let mut postings: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        let mut documents = vec![];
+
+        for (w, website) in websites.iter().enumerate() {
+            for (a, article) in website.articles.iter().enumerate() {
+                let document = documents.len();
+                documents.push((w, a));
+
+                let words: HashSet<String> = terms(&article.title)
+                    .chain(terms(&article.author))
+                    .chain(terms(&strip_tags(
+                        article.subtitle.as_deref().unwrap_or_default(),
+                    )))
+                    .chain(terms(&strip_tags(&article.content)))
+                    .collect();
+
+                for word in words {
+                    postings.entry(word).or_default().push(document);
+                }
+            }
+        }
+
+        Index {
+            postings,
+            documents,
+        }
+    }
+
+    /// Articles containing every term of the query, newest first. Each term matches the words it
+    /// is a prefix of so results show up while the query is still being typed.
+    pub fn search(&self, websites: &[Website], query: &str) -> Vec<Article> {
+        let mut matches: Option<HashSet<usize>> = None;
+
+        for term in terms(query) {
+            let found: HashSet<usize> = self
+                .postings
+                .range::<str, _>((Bound::Included(term.as_str()), Bound::Unbounded))
+                .take_while(|(word, _)| word.starts_with(&term))
+                .flat_map(|(_, documents)| documents.iter().copied())
+                .collect();
+
+            matches = Some(match matches {
+                Some(previous) => previous.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+
+        let mut articles: Vec<Article> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .map(|document| {
+                let (w, a) = self.documents[document];
+                websites[w].articles[a].clone()
+            })
+            .collect();
+
+        articles.sort_by_cached_key(|article| Reverse(article.published()));
+        articles
+    }
+}
+
+/// Splits text into lowercase words
+pub(crate) fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Byte ranges of `text` matching any of the terms, ignoring case like `terms` does
+pub(crate) fn matches(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    // lowercasing can change the length of a character, so every byte of the haystack keeps the
+    // range of the character of `text` it comes from
+    let mut haystack = String::with_capacity(text.len());
+    let mut origins: Vec<Range<usize>> = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        let origin = start..start + c.len_utf8();
+        for lower in c.to_lowercase() {
+            haystack.push(lower);
+            origins.resize(haystack.len(), origin.clone());
+        }
+    }
+
+    let mut ranges: Vec<Range<usize>> = terms
+        .iter()
+        .filter(|term| !term.is_empty())
+        .flat_map(|term| {
+            haystack
+                .match_indices(term.as_str())
+                .map(|(start, found)| origins[start].start..origins[start + found.len() - 1].end)
+        })
+        .collect();
+
+    ranges.sort_by_key(|range| range.start);
+    // overlapping matches of different terms are merged into one
+    ranges.into_iter().fold(vec![], |mut merged, range| {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+        merged
+    })
+}
+
+/// Drops the markup of HTML so tag names and attributes stay out of the index
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found<'a>(text: &'a str, query: &str) -> Vec<&'a str> {
+        let terms: Vec<String> = terms(query).collect();
+        matches(text, &terms)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn matches_non_ascii_uppercase() {
+        assert_eq!(found("Éclair and ÜBER", "éclair über"), ["Éclair", "ÜBER"]);
+        assert_eq!(found("Привет, МИР", "мир"), ["МИР"]);
+    }
+
+    #[test]
+    fn maps_offsets_when_lowercase_is_longer() {
+        // `İ` lowercases to two characters
+        assert_eq!(
+            found("İstanbul Istanbul", "stanbul"),
+            ["stanbul", "stanbul"]
+        );
+        assert_eq!(found("xİy", "i"), ["İ"]);
+    }
+}
@@ -0,0 +1,369 @@
+//! Serves the subscriptions over HTTP as Atom, RSS 2.0 and JSON feeds, for other programs on the
+//! machine to read: every subscription merged, each folder, each virtual feed and the starred
+//! articles.
+use crate::{
+    cli,
+    config::Config,
+    error::Error,
+    export,
+    query::Query,
+    rss::{Article, Website},
+    starred, state,
+    timeline::Timeline,
+};
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashSet},
+    io::Cursor,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+/// Formats every feed is served in, chosen by the extension of the path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Atom,
+    Rss,
+    Json,
+}
+
+impl Format {
+    const ALL: [Format; 3] = [Format::Atom, Format::Rss, Format::Json];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Atom => "atom",
+            Format::Rss => "rss",
+            Format::Json => "json",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Atom => "application/atom+xml; charset=utf-8",
+            Format::Rss => "application/rss+xml; charset=utf-8",
+            Format::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+/// Articles a feed serves
+enum Source {
+    All,
+    Folder(String),
+    /// The query of a virtual feed
+    Virtual(String),
+    Starred,
+}
+
+/// A feed served, at its path followed by the extension of a format
+struct Feed {
+    path: String,
+    title: String,
+    source: Source,
+}
+
+/// Fetches the subscriptions, again every refresh interval, and answers requests until the process
+/// is stopped
+pub(crate) fn run(config: Config, bind: &str) -> Result<(), Error> {
+    let server = Server::http(bind).map_err(|e| Error::ServeError(e.to_string()))?;
+    let websites = Arc::new(RwLock::new(cli::fetch(&config, &[])?));
+    let config = Arc::new(config);
+
+    if config.refresh.interval > 0 {
+        let (config, websites) = (config.clone(), websites.clone());
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(config.refresh.interval * 60));
+            match cli::fetch(&config, &[]) {
+                Ok(fetched) => match websites.write() {
+                    Ok(mut websites) => *websites = fetched,
+                    Err(_) => return,
+                },
+                Err(error) => eprintln!("Could not refresh the feeds: {}", error),
+            }
+        });
+    }
+
+    println!("Serving the feeds at http://{}/", server.server_addr());
+    for request in server.incoming_requests() {
+        let response = match websites.read() {
+            Ok(websites) => respond(&request, &config, &websites),
+            Err(_) => text(StatusCode(500), "The feeds are unavailable"),
+        };
+        if let Err(error) = request.respond(response) {
+            eprintln!("Could not answer a request: {}", error);
+        }
+    }
+    Ok(())
+}
+
+fn respond(request: &Request, config: &Config, websites: &[Website]) -> Response<Cursor<Vec<u8>>> {
+    if !matches!(request.method(), Method::Get | Method::Head) {
+        return text(StatusCode(405), "Only GET and HEAD are supported");
+    }
+    let path = request.url().split('?').next().unwrap_or_default();
+    if path == "/" {
+        return response(index(config), "text/html; charset=utf-8");
+    }
+
+    // names of feeds keep their letters, which clients send percent-encoded
+    let found = percent_decode(path)
+        .as_deref()
+        .and_then(|path| path.strip_prefix('/'))
+        .and_then(|path| path.rsplit_once('.'))
+        .and_then(|(name, extension)| {
+            let format = Format::ALL
+                .into_iter()
+                .find(|format| format.extension() == extension)?;
+            Some((name.to_string(), format))
+        });
+    let Some((name, format)) = found else {
+        return text(StatusCode(404), "No feed here, the list is at /");
+    };
+    let (title, articles) = match articles(&name, config, websites) {
+        Ok(Some(found)) => found,
+        Ok(None) => return text(StatusCode(404), "No feed here, the list is at /"),
+        Err(error) => return text(StatusCode(500), &error.to_string()),
+    };
+
+    let host = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Host"))
+        .map(|header| header.value.to_string())
+        .unwrap_or_default();
+    let url = format!("http://{}{}", host, path);
+    let body = match format {
+        Format::Atom => export::atom(&title, &articles),
+        Format::Rss => export::rss(&title, &format!("http://{}/", host), &articles),
+        Format::Json => match export::json_feed(&title, &url, &articles) {
+            Ok(body) => body,
+            Err(error) => return text(StatusCode(500), &error.to_string()),
+        },
+    };
+    response(body, format.content_type())
+}
+
+/// Every feed served
+fn feeds(config: &Config) -> Vec<Feed> {
+    let mut paths: HashSet<String> = HashSet::new();
+    let mut feeds = vec![Feed {
+        path: unique(&mut paths, "", "all"),
+        title: Timeline::All.name().to_string(),
+        source: Source::All,
+    }];
+    let folders: BTreeSet<&str> = config
+        .folders
+        .iter()
+        .map(|folder| folder.name.as_str())
+        .collect();
+    for folder in folders {
+        feeds.push(Feed {
+            path: unique(&mut paths, "tag/", &export::slug(folder)),
+            title: folder.to_string(),
+            source: Source::Folder(folder.to_string()),
+        });
+    }
+    for feed in &config.virtual_feeds {
+        feeds.push(Feed {
+            path: unique(&mut paths, "virtual/", &export::slug(&feed.name)),
+            title: feed.name.clone(),
+            source: Source::Virtual(feed.query.clone()),
+        });
+    }
+    feeds.push(Feed {
+        path: unique(&mut paths, "", "starred"),
+        title: "Starred".to_string(),
+        source: Source::Starred,
+    });
+    feeds
+}
+
+/// A path no other feed has: names that slug the same, like "C" and "C++", get a number, and
+/// names without letters or digits get one instead
+fn unique(paths: &mut HashSet<String>, prefix: &str, slug: &str) -> String {
+    let path = (1..)
+        .map(|number| match (slug.is_empty(), number) {
+            (false, 1) => format!("{}{}", prefix, slug),
+            (false, _) => format!("{}{}-{}", prefix, slug, number),
+            (true, _) => format!("{}{}", prefix, number),
+        })
+        .find(|path| !paths.contains(path))
+        .unwrap_or_default();
+    paths.insert(path.clone());
+    path
+}
+
+/// Title and articles, newest first, of the feed at a path
+fn articles(
+    path: &str,
+    config: &Config,
+    websites: &[Website],
+) -> Result<Option<(String, Vec<Article>)>, Error> {
+    let Some(feed) = feeds(config).into_iter().find(|feed| feed.path == path) else {
+        return Ok(None);
+    };
+
+    let articles = match &feed.source {
+        Source::All => Timeline::All.articles(websites, &state::load()?),
+        Source::Starred => starred::load()?.articles,
+        Source::Folder(folder) => {
+            let mut articles: Vec<Article> = websites
+                .iter()
+                .filter(|website| website.tags.contains(folder))
+                .flat_map(|website| website.articles.iter().cloned())
+                .collect();
+            articles.sort_by_cached_key(|article| Reverse(article.published()));
+            articles
+        }
+        Source::Virtual(query) => match Query::parse(query) {
+            Ok(query) => query.articles(websites, &state::load()?),
+            Err(error) => {
+                eprintln!("Virtual feed {}: {}", feed.title, error);
+                return Ok(None);
+            }
+        },
+    };
+    Ok(Some((feed.title, articles)))
+}
+
+/// A page linking every feed, which feed readers can also discover them from
+fn index(config: &Config) -> String {
+    let feeds = feeds(config);
+    let mut head = String::new();
+    let mut body = String::new();
+    for feed in &feeds {
+        let title = encode_text(&feed.title);
+        head.push_str(&format!(
+            "<link rel=\"alternate\" type=\"application/atom+xml\" title=\"{}\" href=\"/{}.atom\">\n",
+            encode_double_quoted_attribute(&feed.title),
+            feed.path
+        ));
+        let links: Vec<String> = Format::ALL
+            .iter()
+            .map(|format| {
+                format!(
+                    "<a href=\"/{}.{}\">{}</a>",
+                    feed.path,
+                    format.extension(),
+                    format.extension()
+                )
+            })
+            .collect();
+        body.push_str(&format!("<li>{} · {}</li>\n", title, links.join(" · ")));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{crate}</title>
+{head}</head>
+<body>
+<h1>{crate}</h1>
+<ul>
+{body}</ul>
+</body>
+</html>
+"#,
+        crate = env!("CARGO_CRATE_NAME"),
+        head = head,
+        body = body
+    )
+}
+
+/// Decodes the `%XX` escapes of a path, `None` when the bytes they stand for are not UTF-8
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = path
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn response(body: String, content_type: &str) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", content_type).expect("valid header");
+    Response::from_string(body).with_header(header)
+}
+
+fn text(status: StatusCode, message: &str) -> Response<Cursor<Vec<u8>>> {
+    response(message.to_string(), "text/plain; charset=utf-8").with_status_code(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Folder;
+
+    #[test]
+    fn serves_folders_named_with_non_ascii_letters() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        let config = Config {
+            folders: vec![Folder {
+                name: "Café".to_string(),
+                subscriptions: vec![],
+            }],
+            ..Default::default()
+        };
+
+        let client = thread::spawn(move || {
+            let response = reqwest::blocking::Client::builder()
+                .no_proxy()
+                .build()
+                .unwrap()
+                .get(format!("http://{}/tag/caf%C3%A9.atom", address))
+                .send()
+                .unwrap();
+            (response.status().as_u16(), response.text().unwrap())
+        });
+        let request = server.recv().unwrap();
+        let response = respond(&request, &config, &[]);
+        request.respond(response).unwrap();
+
+        let (status, body) = client.join().unwrap();
+        assert_eq!(status, 200);
+        assert!(body.contains("<title>Café</title>"));
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(
+            percent_decode("/tag/caf%C3%A9.atom").unwrap(),
+            "/tag/café.atom"
+        );
+        assert_eq!(percent_decode("/100%.atom").unwrap(), "/100%.atom");
+        assert_eq!(percent_decode("/%FF.atom"), None);
+    }
+
+    #[test]
+    fn colliding_and_empty_slugs_get_distinct_paths() {
+        let mut paths = HashSet::new();
+        let paths: Vec<String> = ["C++", "C", "★", "☆"]
+            .iter()
+            .map(|name| unique(&mut paths, "tag/", &export::slug(name)))
+            .collect();
+        assert_eq!(paths, ["tag/c", "tag/c-2", "tag/1", "tag/2"]);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{error::Error, rss::Article};
+
+use serde::{Deserialize, Serialize};
+
+const STARRED_NAME: &str = "starred";
+
+/// Articles starred by the user, kept with their content so they outlive their feed. Stored apart
+/// from the state, which is written far more often.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Starred {
+    /// Most recently starred first
+    pub articles: Vec<Article>,
+}
+
+impl Starred {
+    pub fn contains(&self, article: &Article) -> bool {
+        self.articles
+            .iter()
+            .any(|starred| starred.key() == article.key())
+    }
+
+    /// Stars an article or takes its star away, returns whether it is starred now
+    pub fn toggle(&mut self, article: &Article) -> bool {
+        if self.contains(article) {
+            self.articles
+                .retain(|starred| starred.key() != article.key());
+            false
+        } else {
+            self.articles.insert(0, article.clone());
+            true
+        }
+    }
+}
+
+pub(crate) fn store(starred: &Starred) -> Result<(), Error> {
+    confy::store(env!("CARGO_CRATE_NAME"), STARRED_NAME, starred)?;
+    Ok(())
+}
+
+/// Loads the starred articles. A file that does not parse is reported rather than replaced, they
+/// are kept for good.
+pub(crate) fn load() -> Result<Starred, Error> {
+    Ok(confy::load(env!("CARGO_CRATE_NAME"), STARRED_NAME)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_id_in_two_feeds_is_starred_apart() {
+        let article = |source: &str| Article {
+            id: "1".to_string(),
+            source: source.to_string(),
+            ..Default::default()
+        };
+        let (first, second) = (
+            article("https://a.example/feed"),
+            article("https://b.example/feed"),
+        );
+
+        let mut starred = Starred::default();
+        assert!(starred.toggle(&first));
+        assert!(!starred.contains(&second));
+        assert!(starred.toggle(&second));
+        assert!(!starred.toggle(&first));
+        assert!(starred.contains(&second));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{error::Error, rss::Article};
+
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+const STATE_NAME: &str = "state";
+
+/// Everything trss remembers between runs that is not configuration
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    /// Keys of the articles that have been opened, see `Article::key`
+    pub read: HashSet<String>,
+    /// Keys of the articles a filter rule marks read that the user marked unread again
+    pub unread: HashSet<String>,
+    /// Names of the folders collapsed in the sidebar
+    pub collapsed: HashSet<String>,
+    /// Subscriptions failing to fetch, by URL
+    pub failures: BTreeMap<String, Failure>,
+    /// URLs of the dead subscriptions that are not fetched anymore
+    pub paused: HashSet<String>,
+    /// URLs of the enclosures downloaded, so they are not downloaded again once deleted
+    pub downloaded: HashSet<String>,
+}
+
+/// Fetches of a subscription that failed in a row
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Failure {
+    pub count: u32,
+    /// When the first of them failed
+    pub since: Timestamp,
+    pub error: String,
+    /// The server answered 410 Gone, the subscription should be removed
+    #[serde(default)]
+    pub gone: bool,
+}
+
+impl State {
+    /// Articles marked read by a filter rule stay read, unless the user marked them unread. Bare
+    /// ids are marks kept before they were told apart by feed.
+    pub fn is_read(&self, article: &Article) -> bool {
+        let key = article.key();
+        if self.unread.contains(&key) {
+            return false;
+        }
+        article.read || self.read.contains(&key) || self.read.contains(&article.id)
+    }
+
+    pub fn mark_read(&mut self, article: &Article) {
+        let key = article.key();
+        self.unread.remove(&key);
+        self.read.insert(key);
+    }
+
+    pub fn mark_unread(&mut self, article: &Article) {
+        self.read.remove(&article.key());
+        self.read.remove(&article.id);
+        if article.read {
+            self.unread.insert(article.key());
+        }
+    }
+
+    pub fn record_failure(&mut self, url: &str, error: String, gone: bool) {
+        let failure = self
+            .failures
+            .entry(url.to_string())
+            .or_insert_with(|| Failure {
+                count: 0,
+                since: Timestamp::now(),
+                error: String::new(),
+                gone: false,
+            });
+        failure.count += 1;
+        failure.error = error;
+        failure.gone = gone;
+    }
+
+    /// Forgets the failures of a subscription that answered again, returns `false` when there
+    /// were none
+    pub fn record_success(&mut self, url: &str) -> bool {
+        let paused = self.paused.remove(url);
+        self.failures.remove(url).is_some() || paused
+    }
+
+    /// Forgets everything about a subscription that moved or was removed
+    pub fn forget(&mut self, url: &str) {
+        self.failures.remove(url);
+        self.paused.remove(url);
+    }
+
+    /// Whether the subscription has been failing for at least `days` days
+    pub fn is_dead(&self, url: &str, days: u32) -> bool {
+        days > 0
+            && self.failures.get(url).is_some_and(|failure| {
+                Timestamp::now().duration_since(failure.since)
+                    >= SignedDuration::from_hours(i64::from(days) * 24)
+            })
+    }
+}
+
+pub(crate) fn store(state: &State) -> Result<(), Error> {
+    confy::store(env!("CARGO_CRATE_NAME"), STATE_NAME, state)?;
+    Ok(())
+}
+
+/// Loads the state. A file that does not parse is reported rather than replaced, so the read marks
+/// are not lost.
+pub(crate) fn load() -> Result<State, Error> {
+    Ok(confy::load(env!("CARGO_CRATE_NAME"), STATE_NAME)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(source: &str) -> Article {
+        Article {
+            id: "1".to_string(),
+            source: source.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_same_id_in_two_feeds_is_read_apart() {
+        let (first, second) = (
+            article("https://a.example/feed"),
+            article("https://b.example/feed"),
+        );
+        let mut state = State::default();
+        state.mark_read(&first);
+        assert!(state.is_read(&first));
+        assert!(!state.is_read(&second));
+    }
+
+    #[test]
+    fn bare_ids_are_still_read_until_marked_unread() {
+        let article = article("https://a.example/feed");
+        let mut state = State::default();
+        state.read.insert("1".to_string());
+        assert!(state.is_read(&article));
+        state.mark_unread(&article);
+        assert!(!state.is_read(&article));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{
+    rss::{Article, Website},
+    state::State,
+};
+
+use std::cmp::Reverse;
+
+/// Virtual feeds merging the articles of every subscription into one date-sorted list
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Timeline {
+    All,
+    Today,
+    Unread,
+}
+
+impl Timeline {
+    pub const ALL: [Timeline; 3] = [Timeline::All, Timeline::Today, Timeline::Unread];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Timeline::All => "All articles",
+            Timeline::Today => "Today",
+            Timeline::Unread => "Unread",
+        }
+    }
+
+    /// Collects the matching articles of every website, newest first
+    pub fn articles(&self, websites: &[Website], state: &State) -> Vec<Article> {
+        let start_of_day = jiff::Zoned::now()
+            .start_of_day()
+            .map(|day| day.timestamp())
+            .ok();
+
+        let mut articles: Vec<Article> = websites
+            .iter()
+            .flat_map(|website| website.articles.iter())
+            .filter(|article| match self {
+                Timeline::All => true,
+                Timeline::Today => match (article.published(), start_of_day) {
+                    (Some(published), Some(start)) => published >= start,
+                    _ => false,
+                },
+                Timeline::Unread => !state.is_read(article),
+            })
+            .cloned()
+            .collect();
+
+        articles.sort_by_cached_key(|article| Reverse(article.published()));
+        articles
+    }
+}